@@ -0,0 +1,307 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
+use crate::per_cpu::CpuId;
+
+/// Set in every CPU's reader slot while a writer holds or is acquiring the lock.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// One CPU's reader count, on its own cache line.
+#[repr(align(64))]
+struct ReaderSlot(AtomicUsize);
+
+/// A "big reader" lock whose guards disable interrupts properly,
+/// for data that is read very often on all CPUs but rarely written.
+///
+/// Unlike [`RwLockIrqSafe`](crate::RwLockIrqSafe), which has a single reader count
+/// shared by all CPUs, this has one reader count per CPU, each on its own cache line.
+/// Thus, `read()` only touches the current CPU's cache line, whereas `write()`
+/// must acquire every CPU's reader count, making it much more expensive.
+/// A waiting writer prevents new readers from acquiring the lock.
+///
+/// The current CPU is determined by the user-supplied [`CpuId`] implementation `C`,
+/// which must return a value less than `CPUS`.
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::{BrLockIrqSafe, CpuId};
+///
+/// struct MyCpuId;
+/// unsafe impl CpuId for MyCpuId {
+///     fn current_cpu() -> usize { 0 /* read from hardware */ }
+/// }
+///
+/// static CONFIG: BrLockIrqSafe<u32, 4, MyCpuId> = BrLockIrqSafe::new(100);
+///
+/// fn on_interrupt() -> u32 {
+///     *CONFIG.read()
+/// }
+///
+/// fn reconfigure() {
+///     *CONFIG.write() = 200;
+/// }
+/// ```
+pub struct BrLockIrqSafe<T: ?Sized, const CPUS: usize, C: CpuId> {
+    readers: [ReaderSlot; CPUS],
+    /// Serializes writers.
+    writer: AtomicBool,
+    _cpu_id: PhantomData<fn() -> C>,
+    data: UnsafeCell<T>,
+}
+
+/// A guard to which the protected data can be read
+///
+/// When the guard falls out of scope it will decrement the current CPU's read count,
+/// potentially re-enabling interrupts.
+pub struct BrLockIrqSafeReadGuard<'a, T: 'a + ?Sized, const CPUS: usize, C: CpuId> {
+    brlock: &'a BrLockIrqSafe<T, CPUS, C>,
+    slot: &'a AtomicUsize,
+    // `_held_irq` will be dropped after the read count is decremented in `Drop::drop()`.
+    _held_irq: HeldInterrupts,
+}
+
+/// A guard to which the protected data can be written
+///
+/// When the guard falls out of scope it will release the lock and potentially re-enable interrupts.
+pub struct BrLockIrqSafeWriteGuard<'a, T: 'a + ?Sized, const CPUS: usize, C: CpuId> {
+    brlock: &'a BrLockIrqSafe<T, CPUS, C>,
+    // `_held_irq` will be dropped after the lock is released in `Drop::drop()`.
+    _held_irq: HeldInterrupts,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send, const CPUS: usize, C: CpuId> Send for BrLockIrqSafe<T, CPUS, C> {}
+unsafe impl<T: ?Sized + Send + Sync, const CPUS: usize, C: CpuId> Sync for BrLockIrqSafe<T, CPUS, C> {}
+
+impl<T, const CPUS: usize, C: CpuId> BrLockIrqSafe<T, CPUS, C> {
+    /// Creates a new big reader lock wrapping the supplied data.
+    pub const fn new(data: T) -> BrLockIrqSafe<T, CPUS, C> {
+        BrLockIrqSafe {
+            readers: [const { ReaderSlot(AtomicUsize::new(0)) }; CPUS],
+            writer: AtomicBool::new(false),
+            _cpu_id: PhantomData,
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this `BrLockIrqSafe`, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized, const CPUS: usize, C: CpuId> BrLockIrqSafe<T, CPUS, C> {
+    /// Locks this brlock with shared read access, blocking the current thread
+    /// until it can be acquired.
+    ///
+    /// This only touches the current CPU's reader count.
+    ///
+    /// Returns an RAII guard which will release this thread's shared access
+    /// once it is dropped, along with restoring interrupts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current CPU is not less than `CPUS`.
+    #[inline]
+    pub fn read(&self) -> BrLockIrqSafeReadGuard<'_, T, CPUS, C> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Attempt to acquire this lock with shared read access.
+    ///
+    /// This function will never block and will return `None` if a writer
+    /// holds or is waiting for the lock, unless the current CPU already holds it for reading.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current CPU is not less than `CPUS`.
+    #[inline]
+    pub fn try_read(&self) -> Option<BrLockIrqSafeReadGuard<'_, T, CPUS, C>> {
+        let _held_irq = hold_interrupts();
+        let slot = &self.readers[C::current_cpu()].0;
+        // Since readers hold interrupts, any existing readers on this CPU belong to the current task,
+        // so nested reads can proceed even if a writer is waiting for them to finish.
+        if slot.fetch_add(1, Ordering::Acquire) == WRITER {
+            slot.fetch_sub(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(BrLockIrqSafeReadGuard { brlock: self, slot, _held_irq })
+    }
+
+    /// Locks this brlock with shared read access and invokes `f` with the protected data,
+    /// returning its result.
+    #[inline]
+    pub fn with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&*self.read())
+    }
+
+    /// Attempts to lock this brlock with shared read access and,
+    /// if successful, invokes `f` with the protected data.
+    #[inline]
+    pub fn try_with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        self.try_read().map(|guard| f(&*guard))
+    }
+
+    /// Return the number of readers that currently hold the lock across all CPUs.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    pub fn reader_count(&self) -> usize {
+        self.readers.iter().map(|slot| slot.0.load(Ordering::Relaxed) & !WRITER).sum()
+    }
+
+    /// Return the number of writers that currently hold or are acquiring the lock.
+    ///
+    /// This function may only return either `0` or `1`.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    pub fn writer_count(&self) -> usize {
+        self.writer.load(Ordering::Relaxed) as usize
+    }
+
+    /// Lock this brlock with exclusive write access, blocking the current
+    /// thread until it can be acquired.
+    ///
+    /// Once no other writer holds the lock, this prevents new readers on all CPUs
+    /// from acquiring it, then waits for existing readers to release it.
+    ///
+    /// Returns an RAII guard which will drop the write access of this brlock
+    /// when dropped.
+    #[inline]
+    pub fn write(&self) -> BrLockIrqSafeWriteGuard<'_, T, CPUS, C> {
+        let _held_irq = loop {
+            let held_irq = hold_interrupts();
+            if self.writer.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+                break held_irq;
+            }
+            drop(held_irq);
+            spin_loop();
+        };
+        for slot in &self.readers {
+            slot.0.fetch_or(WRITER, Ordering::Relaxed);
+        }
+        for slot in &self.readers {
+            while slot.0.load(Ordering::Acquire) != WRITER {
+                spin_loop();
+            }
+        }
+        BrLockIrqSafeWriteGuard { brlock: self, _held_irq }
+    }
+
+    /// Attempt to lock this brlock with exclusive write access.
+    ///
+    /// This function does not ever block, and it will return `None` if a call
+    /// to `write` would otherwise block. If successful, an RAII guard is
+    /// returned.
+    #[inline]
+    pub fn try_write(&self) -> Option<BrLockIrqSafeWriteGuard<'_, T, CPUS, C>> {
+        let _held_irq = hold_interrupts();
+        if self.writer.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return None;
+        }
+        for (i, slot) in self.readers.iter().enumerate() {
+            if slot.0.compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed).is_err() {
+                for slot in &self.readers[..i] {
+                    slot.0.fetch_and(!WRITER, Ordering::Release);
+                }
+                self.writer.store(false, Ordering::Release);
+                return None;
+            }
+        }
+        Some(BrLockIrqSafeWriteGuard { brlock: self, _held_irq })
+    }
+
+    /// Locks this brlock with exclusive write access and invokes `f` with the protected data,
+    /// returning its result.
+    #[inline]
+    pub fn with_write<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut *self.write())
+    }
+
+    /// Attempts to lock this brlock with exclusive write access and,
+    /// if successful, invokes `f` with the protected data.
+    #[inline]
+    pub fn try_with_write<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        self.try_write().map(|mut guard| f(&mut *guard))
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`BrLockIrqSafe`] mutably, no actual locking needs to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, const CPUS: usize, C: CpuId> fmt::Debug for BrLockIrqSafe<T, CPUS, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "BrLockIrqSafe {{ data: {:?} }}", &*guard),
+            None => write!(f, "BrLockIrqSafe {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default, const CPUS: usize, C: CpuId> Default for BrLockIrqSafe<T, CPUS, C> {
+    fn default() -> BrLockIrqSafe<T, CPUS, C> {
+        BrLockIrqSafe::new(Default::default())
+    }
+}
+
+impl<'brlock, T: ?Sized, const CPUS: usize, C: CpuId> Deref for BrLockIrqSafeReadGuard<'brlock, T, CPUS, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: no writer can hold the lock while this CPU's read count is non-zero.
+        unsafe { &*self.brlock.data.get() }
+    }
+}
+
+impl<'brlock, T: ?Sized, const CPUS: usize, C: CpuId> Drop for BrLockIrqSafeReadGuard<'brlock, T, CPUS, C> {
+    fn drop(&mut self) {
+        self.slot.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<'brlock, T: ?Sized, const CPUS: usize, C: CpuId> Deref for BrLockIrqSafeWriteGuard<'brlock, T, CPUS, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: this guard has exclusive access.
+        unsafe { &*self.brlock.data.get() }
+    }
+}
+
+impl<'brlock, T: ?Sized, const CPUS: usize, C: CpuId> DerefMut for BrLockIrqSafeWriteGuard<'brlock, T, CPUS, C> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: this guard has exclusive access.
+        unsafe { &mut *self.brlock.data.get() }
+    }
+}
+
+impl<'brlock, T: ?Sized, const CPUS: usize, C: CpuId> Drop for BrLockIrqSafeWriteGuard<'brlock, T, CPUS, C> {
+    fn drop(&mut self) {
+        for slot in &self.brlock.readers {
+            slot.0.fetch_and(!WRITER, Ordering::Release);
+        }
+        self.brlock.writer.store(false, Ordering::Release);
+    }
+}
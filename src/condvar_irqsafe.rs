@@ -0,0 +1,205 @@
+use core::{
+    fmt,
+    hint::spin_loop,
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use crate::mutex_irqsafe::MutexIrqSafeGuard;
+
+/// A hook that lets the OS block a waiting task instead of busy-waiting.
+///
+/// All functions receive a `key`, which is the address of the [`CondvarIrqSafe`]
+/// being waited on, such that the OS can maintain one wait queue per condvar
+/// (similar to a futex).
+///
+/// Implementations of [`Parker::unpark_one()`] and [`Parker::unpark_all()`]
+/// may be invoked from an interrupt handler, so they must not block.
+pub trait Parker {
+    /// Blocks the current task on the wait queue identified by `key`.
+    ///
+    /// To avoid lost wakeups, the implementation must call `should_park()`
+    /// *after* the current task has been added to the wait queue
+    /// (or while holding the wait queue's lock), and must return immediately
+    /// without blocking if it returns `false`.
+    ///
+    /// Spurious returns are permitted.
+    fn park(key: usize, should_park: &dyn Fn() -> bool);
+
+    /// Wakes up at least one task blocked on the wait queue identified by `key`, if any.
+    fn unpark_one(key: usize);
+
+    /// Wakes up all tasks blocked on the wait queue identified by `key`.
+    fn unpark_all(key: usize);
+}
+
+/// The default [`Parker`], which simply busy-waits.
+pub struct SpinParker;
+
+impl Parker for SpinParker {
+    #[inline(always)]
+    fn park(_key: usize, _should_park: &dyn Fn() -> bool) {
+        spin_loop();
+    }
+
+    #[inline(always)]
+    fn unpark_one(_key: usize) { }
+
+    #[inline(always)]
+    fn unpark_all(_key: usize) { }
+}
+
+/// A condition variable for use with [`MutexIrqSafe`](crate::MutexIrqSafe).
+///
+/// Waiting on this condvar atomically releases the lock and restores interrupts
+/// to the state they were in before the lock was acquired,
+/// such that an interrupt handler can signal the condition.
+/// Both the lock and the interrupt hold are re-acquired before returning from a wait.
+///
+/// The notify functions never block and are safe to call from an interrupt handler.
+///
+/// By default, waiting tasks busy-wait; an OS can supply its own [`Parker`]
+/// to block waiting tasks instead.
+///
+/// Note that if interrupts were already disabled before the lock was acquired,
+/// they will *not* be re-enabled while waiting, so the condition cannot be
+/// signaled by an interrupt handler on the current CPU.
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::{CondvarIrqSafe, MutexIrqSafe};
+///
+/// static DONE: MutexIrqSafe<bool> = MutexIrqSafe::new(false);
+/// static DONE_CONDVAR: CondvarIrqSafe = CondvarIrqSafe::new();
+///
+/// // Invoked from the device's interrupt handler.
+/// fn on_completion_interrupt() {
+///     *DONE.lock() = true;
+///     DONE_CONDVAR.notify_one();
+/// }
+///
+/// fn wait_for_completion() {
+///     let done = DONE_CONDVAR.wait_while(DONE.lock(), |done| !*done);
+///     assert!(*done);
+/// }
+/// ```
+pub struct CondvarIrqSafe<P: Parker = SpinParker> {
+    /// Incremented upon every call to `notify_all()`.
+    generation: AtomicUsize,
+    /// The number of tasks currently waiting.
+    waiters: AtomicUsize,
+    /// The number of pending wakeups issued by `notify_one()`.
+    tokens: AtomicUsize,
+    _parker: PhantomData<fn() -> P>,
+}
+
+impl<P: Parker> CondvarIrqSafe<P> {
+    /// Creates a new condition variable with no waiters.
+    pub const fn new() -> CondvarIrqSafe<P> {
+        CondvarIrqSafe {
+            generation: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            tokens: AtomicUsize::new(0),
+            _parker: PhantomData,
+        }
+    }
+
+    /// Blocks the current task until this condvar is notified.
+    ///
+    /// The lock held by `guard` is released (and interrupts are restored)
+    /// while waiting, and are then re-acquired before this function returns.
+    ///
+    /// Like other condition variables, this function may wake up spuriously,
+    /// so it should typically be used in a loop; see [`Self::wait_while()`].
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexIrqSafeGuard<'a, T>) -> MutexIrqSafeGuard<'a, T> {
+        let mutex = guard.mutex;
+        // Register as a waiter while still holding the lock,
+        // such that a notification issued after we release it cannot be missed.
+        let generation = self.generation.load(Ordering::Acquire);
+        self.waiters.fetch_add(1, Ordering::AcqRel);
+        drop(guard);
+
+        let key = self as *const _ as *const () as usize;
+        while !self.try_consume_wakeup(generation) {
+            P::park(key, &|| !self.wakeup_pending(generation));
+        }
+
+        self.waiters.fetch_sub(1, Ordering::AcqRel);
+        mutex.lock()
+    }
+
+    /// Blocks the current task for as long as `condition` returns `true`.
+    ///
+    /// The `condition` is checked with the lock held, both initially
+    /// and after every wakeup. Returns the guard once `condition` returns `false`.
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexIrqSafeGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexIrqSafeGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wakes up one task waiting on this condvar, if there is one.
+    ///
+    /// This never blocks and may be called from an interrupt handler.
+    /// Which waiting task is woken up is unspecified.
+    pub fn notify_one(&self) {
+        let waiters = self.waiters.load(Ordering::Acquire);
+        let issued = self.tokens.fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+            if tokens < waiters { Some(tokens + 1) } else { None }
+        });
+        if issued.is_ok() {
+            P::unpark_one(self as *const _ as *const () as usize);
+        }
+    }
+
+    /// Wakes up all tasks waiting on this condvar.
+    ///
+    /// This never blocks and may be called from an interrupt handler.
+    pub fn notify_all(&self) {
+        if self.waiters.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        // All current waiters are woken up by the new generation, so any tokens issued to them
+        // must not be left over for later waiters, which would then wake up spuriously.
+        // They are discarded first, such that tokens issued to later waiters are kept.
+        self.tokens.store(0, Ordering::Release);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        P::unpark_all(self as *const _ as *const () as usize);
+    }
+
+    /// Returns whether a wakeup is available to a waiter that started waiting during `generation`.
+    fn wakeup_pending(&self, generation: usize) -> bool {
+        self.generation.load(Ordering::Acquire) != generation
+            || self.tokens.load(Ordering::Acquire) > 0
+    }
+
+    /// Attempts to consume a wakeup for a waiter that started waiting during `generation`.
+    fn try_consume_wakeup(&self, generation: usize) -> bool {
+        if self.generation.load(Ordering::Acquire) != generation {
+            return true;
+        }
+        self.tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| tokens.checked_sub(1))
+            .is_ok()
+    }
+}
+
+impl<P: Parker> Default for CondvarIrqSafe<P> {
+    fn default() -> CondvarIrqSafe<P> {
+        CondvarIrqSafe::new()
+    }
+}
+
+impl<P: Parker> fmt::Debug for CondvarIrqSafe<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CondvarIrqSafe {{ waiters: {} }}", self.waiters.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,134 @@
+//! Same-CPU self-deadlock detection for [`MutexIrqSafe`] and [`RwLockIrqSafe`].
+//!
+//! With the `deadlock_detection` feature enabled, each lock records which CPU
+//! holds it exclusively and where it was acquired.
+//! If a CPU then spins on a lock that it already holds exclusively,
+//! e.g., from an interrupt handler that fired while a thread on the same CPU held the lock,
+//! it will never be released, so this panics with the lock's address and both acquisition
+//! locations instead of spinning forever with interrupts disabled.
+//!
+//! Detection is inactive until the current CPU can be determined,
+//! i.e., until [`set_deadlock_detection_cpu_id()`] has been invoked.
+//!
+//! [`MutexIrqSafe`]: crate::MutexIrqSafe
+//! [`RwLockIrqSafe`]: crate::RwLockIrqSafe
+
+use core::{
+    mem,
+    panic::Location,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+#[cfg(feature = "lock_registry")]
+use core::fmt;
+use crate::held_interrupts::hold_interrupts;
+use crate::per_cpu::CpuId;
+
+/// The value of `Owner::cpu` when no CPU holds the lock exclusively.
+const NO_OWNER: usize = usize::MAX;
+
+/// The registered `CpuId::current_cpu` function, as a `fn() -> usize`, or `0` if none.
+static CURRENT_CPU: AtomicUsize = AtomicUsize::new(0);
+
+/// Enables self-deadlock detection, using `C` to determine the current CPU.
+pub fn set_deadlock_detection_cpu_id<C: CpuId>() {
+    let current_cpu: fn() -> usize = C::current_cpu;
+    CURRENT_CPU.store(current_cpu as usize, Ordering::Release);
+}
+
+/// Returns the current CPU, or `None` if no [`CpuId`] has been registered.
+///
+/// Unless interrupts are held by the caller, the result may be out of date.
+fn current_cpu() -> Option<usize> {
+    match CURRENT_CPU.load(Ordering::Acquire) {
+        0 => None,
+        // SAFETY: this was stored from a function pointer of this exact type.
+        f => Some(unsafe { mem::transmute::<usize, fn() -> usize>(f) }()),
+    }
+}
+
+/// The CPU that currently holds a lock exclusively, and where it acquired it.
+pub(crate) struct Owner {
+    cpu: AtomicUsize,
+    location: AtomicPtr<Location<'static>>,
+}
+
+impl Owner {
+    pub(crate) const fn new() -> Owner {
+        Owner {
+            cpu: AtomicUsize::new(NO_OWNER),
+            location: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Records the current CPU as the exclusive owner of a lock that was just acquired.
+    ///
+    /// Interrupts must be held by the caller.
+    /// The returned record must be dropped before the lock is released.
+    pub(crate) fn acquired(&self, location: &'static Location<'static>) -> OwnerRecord<'_> {
+        if let Some(cpu) = current_cpu() {
+            self.location.store(location as *const _ as *mut _, Ordering::Relaxed);
+            self.cpu.store(cpu, Ordering::Relaxed);
+        }
+        OwnerRecord(self)
+    }
+
+    /// Returns whether the current CPU is the exclusive owner of the lock.
+    pub(crate) fn held_by_current_cpu(&self) -> bool {
+        self.owning_cpu().is_some()
+    }
+
+    /// Returns the current CPU if it is the exclusive owner of the lock.
+    ///
+    /// The owner holds interrupts, so if the current context is the owner or interrupted it,
+    /// it cannot migrate and the current CPU can be read without holding interrupts.
+    /// Otherwise, a match may be out of date, so only a match is confirmed with interrupts held,
+    /// which keeps spinning on a lock held by another CPU from holding interrupts every time.
+    fn owning_cpu(&self) -> Option<usize> {
+        let owned_by = |cpu: usize| self.cpu.load(Ordering::Relaxed) == cpu;
+        if !owned_by(current_cpu()?) {
+            return None;
+        }
+        let _held_irq = hold_interrupts();
+        let cpu = current_cpu()?;
+        owned_by(cpu).then_some(cpu)
+    }
+
+    /// Writes the CPU that holds the lock exclusively and where it acquired it, if known,
+    /// for [`dump_held_locks()`](crate::dump_held_locks).
+    #[cfg(feature = "lock_registry")]
+    pub(crate) fn describe(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        let cpu = self.cpu.load(Ordering::Relaxed);
+        if cpu == NO_OWNER {
+            return Ok(());
+        }
+        // SAFETY: any non-null location is `'static`, though it may be out of date.
+        match unsafe { self.location.load(Ordering::Relaxed).as_ref() } {
+            Some(location) => write!(w, ", owned by CPU {} (acquired at {})", cpu, location),
+            None => write!(w, ", owned by CPU {}", cpu),
+        }
+    }
+
+    /// Invoked on every failed iteration of a lock's spin loop.
+    ///
+    /// Panics if the current CPU is the exclusive owner of the lock,
+    /// as it can never be released.
+    pub(crate) fn check(&self, lock: usize, kind: &'static str, location: &'static Location<'static>) {
+        let Some(cpu) = self.owning_cpu() else { return };
+        // SAFETY: this CPU stored the location, which is `'static`, before storing its CPU id.
+        let owner_location = unsafe { &*self.location.load(Ordering::Relaxed) };
+        panic!(
+            "deadlock detected: CPU {} is spinning on {} {:#x} at {}, which it already holds (acquired at {})",
+            cpu, kind, lock, location, owner_location,
+        );
+    }
+}
+
+/// Clears the lock's owner when dropped.
+pub(crate) struct OwnerRecord<'a>(&'a Owner);
+
+impl Drop for OwnerRecord<'_> {
+    fn drop(&mut self) {
+        self.0.cpu.store(NO_OWNER, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,85 @@
+//! The shared implementation of guards that increment a per-CPU counter supplied by the OS,
+//! i.e., [`HeldSoftirqs`](crate::HeldSoftirqs) and [`HeldPreemption`](crate::HeldPreemption).
+
+use core::{
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
+
+/// The hooks registered by the OS for one kind of counter,
+/// each stored as a `fn` pointer, or `0` if none has been registered.
+pub(crate) struct CounterHooks {
+    /// Returns the current CPU's counter, as a `fn() -> &'static AtomicUsize`.
+    local_count: AtomicUsize,
+    /// Invoked after the current CPU's counter drops back to zero, as a `fn()`.
+    reenabled: AtomicUsize,
+}
+
+impl CounterHooks {
+    pub(crate) const fn new() -> CounterHooks {
+        CounterHooks {
+            local_count: AtomicUsize::new(0),
+            reenabled: AtomicUsize::new(0),
+        }
+    }
+
+    /// Registers the given hooks, replacing any previously-registered ones.
+    pub(crate) fn register(&self, local_count: fn() -> &'static AtomicUsize, reenabled: fn()) {
+        self.reenabled.store(reenabled as usize, Ordering::Release);
+        self.local_count.store(local_count as usize, Ordering::Release);
+    }
+
+    /// Increments the current CPU's counter until the returned guard is dropped.
+    ///
+    /// If no hooks have been registered, this holds interrupts instead.
+    pub(crate) fn hold(&'static self) -> HeldCounter {
+        let held_irq = hold_interrupts();
+        match self.local_count.load(Ordering::Acquire) {
+            0 => HeldCounter { hooks: self, count: None, _held_irq: Some(held_irq) },
+            local_count => {
+                // SAFETY: this was stored from a function pointer of this exact type.
+                let local_count = unsafe { mem::transmute::<usize, fn() -> &'static AtomicUsize>(local_count) };
+                // Interrupts are still held, so the current task cannot migrate
+                // to another CPU before its counter is incremented.
+                let count = local_count();
+                count.fetch_add(1, Ordering::Acquire);
+                HeldCounter { hooks: self, count: Some(count), _held_irq: None }
+            }
+        }
+    }
+}
+
+/// Returns a CPU's counter to its prior value when dropped.
+pub(crate) struct HeldCounter {
+    hooks: &'static CounterHooks,
+    /// The counter that was incremented, or `None` if interrupts are being held instead.
+    count: Option<&'static AtomicUsize>,
+    _held_irq: Option<HeldInterrupts>,
+}
+
+impl !Send for HeldCounter {}
+
+impl HeldCounter {
+    /// Returns `true` if this guard holds interrupts because no hooks were registered.
+    pub(crate) fn is_holding_interrupts(&self) -> bool {
+        self.count.is_none()
+    }
+}
+
+impl Drop for HeldCounter {
+    fn drop(&mut self) {
+        let Some(count) = self.count else { return };
+        if count.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        match self.hooks.reenabled.load(Ordering::Acquire) {
+            0 => { }
+            reenabled => {
+                // SAFETY: this was stored from a function pointer of this exact type.
+                let reenabled = unsafe { mem::transmute::<usize, fn()>(reenabled) };
+                reenabled();
+            }
+        }
+    }
+}
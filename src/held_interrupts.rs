@@ -1,5 +1,6 @@
 // Originally inspired by Tifflin OS.
 
+#[cfg(not(any(feature = "unix_signals", feature = "sim", loom)))]
 use core::{
     arch::asm,
     sync::atomic::{compiler_fence, Ordering},
@@ -12,15 +13,105 @@ use core::{
 /// when [`hold_interrupts()`] was invoked, interrupts will be re-enabled
 /// when this type is dropped.
 #[derive(Default)]
-pub struct HeldInterrupts(bool);
+pub struct HeldInterrupts(
+    #[cfg(any(not(feature = "unix_signals"), feature = "sim"))] bool,
+    #[cfg(all(feature = "unix_signals", not(feature = "sim")))] Option<crate::signal_mask::SavedMask>,
+);
 
 impl !Send for HeldInterrupts {}
 
+impl HeldInterrupts {
+    /// Waits for the next interrupt to arrive and be handled,
+    /// returning a guard that restores the same prior interrupt state as this one.
+    ///
+    /// If interrupts were enabled when this guard was created, this atomically
+    /// enables interrupts and waits (see [`enable_interrupts_and_wait()`]),
+    /// such that an interrupt cannot be lost in between checking for work
+    /// and going to sleep. Interrupts are disabled again before this returns.
+    ///
+    /// If interrupts were already disabled when this guard was created,
+    /// they are left disabled, as an outer guard still expects them to be held;
+    /// in that case, this returns immediately.
+    ///
+    /// ```no_run
+    /// # fn run_queue_is_empty() -> bool { true }
+    /// let mut held = irq_safety::hold_interrupts();
+    /// while run_queue_is_empty() {
+    ///     held = held.wait_for_interrupt();
+    /// }
+    /// ```
+    #[cfg(any(not(feature = "unix_signals"), feature = "sim"))]
+    pub fn wait_for_interrupt(self) -> HeldInterrupts {
+        if self.0 {
+            enable_interrupts_and_wait();
+        } else {
+            core::hint::spin_loop();
+        }
+        self
+    }
+
+    /// Waits for the next signal to arrive and be handled,
+    /// returning a guard that unblocks the same signals as this one.
+    ///
+    /// This atomically unblocks the masked signals that were unblocked before this guard was created
+    /// and waits for a signal via `sigsuspend`. The masked signals are blocked again
+    /// before this returns.
+    /// If none of the masked signals were unblocked when this guard was created,
+    /// this returns immediately.
+    #[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+    pub fn wait_for_interrupt(self) -> HeldInterrupts {
+        if let Some(saved) = &self.0 {
+            crate::signal_mask::wait_with_mask(saved);
+        }
+        self
+    }
+
+    /// Temporarily restores the interrupt state from before this guard was created
+    /// while invoking `f`, then holds interrupts again.
+    ///
+    /// If interrupts were enabled when this guard was created, they are enabled
+    /// for the duration of `f`, allowing pending interrupts to be handled,
+    /// e.g., in the middle of a long operation. Otherwise, they remain disabled.
+    /// As when this guard is dropped, enabling interrupts runs the
+    /// [`InterruptsRestoredHook`](crate::InterruptsRestoredHook) if work is pending.
+    ///
+    /// ```no_run
+    /// # fn copy_chunk(_: usize) {}
+    /// let mut held = irq_safety::hold_interrupts();
+    /// for chunk in 0..16 {
+    ///     copy_chunk(chunk);
+    ///     held.unheld(|| { /* pending interrupts can be handled here */ });
+    /// }
+    /// ```
+    #[cfg(any(not(feature = "unix_signals"), feature = "sim"))]
+    pub fn unheld<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        if !self.0 {
+            return f();
+        }
+        restore_interrupts();
+        let result = f();
+        disable_interrupts();
+        result
+    }
+
+    /// Temporarily unblocks the masked signals that were unblocked before this guard was created
+    /// while invoking `f`, then blocks the masked signals again.
+    #[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+    pub fn unheld<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        let Some(saved) = &self.0 else { return f() };
+        restore_signals(saved);
+        let result = f();
+        disable_interrupts();
+        result
+    }
+}
+
 /// Prevents regular interrupts from occurring until the returned
 /// `HeldInterrupts` object is dropped.
 ///
 /// This function only affects *regular* IRQs;
 /// it does not affect NMIs or fast interrupts (FIQs on aarch64).
+#[cfg(any(not(feature = "unix_signals"), feature = "sim"))]
 pub fn hold_interrupts() -> HeldInterrupts {
     let enabled = interrupts_enabled();
     let retval = HeldInterrupts(enabled);
@@ -29,21 +120,98 @@ pub fn hold_interrupts() -> HeldInterrupts {
     retval
 }
 
+/// Prevents the masked signals from being delivered to the current thread
+/// until the returned `HeldInterrupts` object is dropped.
+///
+/// See [`set_masked_signals()`](crate::set_masked_signals) to configure which signals are masked.
+#[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+pub fn hold_interrupts() -> HeldInterrupts {
+    HeldInterrupts(Some(crate::signal_mask::block_signals()))
+}
+
+/// Invokes `f` with interrupts held, returning its result.
+///
+/// Interrupts are restored to their prior state after `f` returns,
+/// such that the interrupt-disabled region is lexically scoped to `f`.
+///
+/// ```no_run
+/// let value = irq_safety::without_interrupts(|| {
+///     // Interrupts are disabled here.
+///     42
+/// });
+/// assert_eq!(value, 42);
+/// ```
+#[inline]
+pub fn without_interrupts<R, F: FnOnce() -> R>(f: F) -> R {
+    let _held_irq = hold_interrupts();
+    f()
+}
+
+/// Enables interrupts, then runs the interrupts-restored hook if work is pending on this CPU.
+#[cfg(any(not(feature = "unix_signals"), feature = "sim"))]
+fn restore_interrupts() {
+    let hook = crate::interrupts_restored::take_pending_hook();
+    enable_interrupts();
+    if let Some(hook) = hook {
+        hook();
+    }
+}
+
+/// Restores the `saved` signal mask, then runs the interrupts-restored hook
+/// if that unblocked any signals and work is pending on this thread's CPU.
+#[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+fn restore_signals(saved: &crate::signal_mask::SavedMask) {
+    let hook = crate::signal_mask::unblocks_any(saved)
+        .then(crate::interrupts_restored::take_pending_hook)
+        .flatten();
+    crate::signal_mask::restore_signals(saved);
+    if let Some(hook) = hook {
+        hook();
+    }
+}
+
 impl Drop for HeldInterrupts {
+    #[cfg(any(not(feature = "unix_signals"), feature = "sim"))]
     fn drop(&mut self) {
         // trace!("hold_interrupts(): enabling interrupts? {}", self.0);
         if self.0 {
-            enable_interrupts();
+            restore_interrupts();
+        }
+    }
+
+    #[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+    fn drop(&mut self) {
+        if let Some(saved) = self.0.take() {
+            restore_signals(&saved);
         }
     }
 }
 
+#[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+pub use crate::signal_mask::{
+    enable_interrupts, disable_interrupts, interrupts_enabled,
+    enable_interrupts_and_halt, enable_interrupts_and_wait,
+};
+
+#[cfg(feature = "sim")]
+pub use crate::sim::{
+    enable_interrupts, disable_interrupts, interrupts_enabled,
+    enable_interrupts_and_halt, enable_interrupts_and_wait,
+};
+
+#[cfg(loom)]
+pub use crate::loom_backend::{
+    enable_interrupts, disable_interrupts, interrupts_enabled,
+    enable_interrupts_and_halt, enable_interrupts_and_wait,
+};
+
 /// Unconditionally enables *regular* interrupts (IRQs),
 /// not NMIs or fast interrupts (FIQs on aarch64).
 ///
 /// To enable fast interrupts (FIQs) on aarch64,
 /// use the [`enable_fast_interrupts()`] interrupts.
 #[inline(always)]
+#[cfg(not(any(feature = "unix_signals", feature = "sim", loom)))]
 pub fn enable_interrupts() {
     compiler_fence(Ordering::SeqCst);
     unsafe {
@@ -65,6 +233,7 @@ pub fn enable_interrupts() {
 /// To disable fast interrupts (FIQs) on aarch64,
 /// use the [`disable_fast_interrupts()`] interrupts.
 #[inline(always)]
+#[cfg(not(any(feature = "unix_signals", feature = "sim", loom)))]
 pub fn disable_interrupts() {
     unsafe {
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
@@ -80,12 +249,64 @@ pub fn disable_interrupts() {
     compiler_fence(Ordering::SeqCst);
 }
 
+/// Atomically enables *regular* interrupts and halts the current CPU
+/// until the next interrupt arrives.
+///
+/// Unlike calling [`enable_interrupts()`] followed by a separate halt instruction,
+/// an interrupt that arrives in between cannot be missed:
+/// * On x86, the `sti` instruction delays enabling interrupts until after the
+///   following `hlt` instruction has started (the "interrupt shadow").
+/// * On aarch64 and arm, `wfi` wakes up when an interrupt becomes pending even if
+///   interrupts are masked, so interrupts are only unmasked after waking up.
+///
+/// Interrupts remain enabled when this returns, after the interrupt has been handled.
+#[inline(always)]
+#[cfg(not(any(feature = "unix_signals", feature = "sim", loom)))]
+pub fn enable_interrupts_and_halt() {
+    compiler_fence(Ordering::SeqCst);
+    unsafe {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        asm!("sti; hlt", options(nomem, nostack));
+
+        #[cfg(target_arch = "aarch64")]
+        asm!("wfi", "msr daifclr, #2", "isb", options(nomem, nostack, preserves_flags));
+
+        #[cfg(target_arch = "arm")]
+        asm!("wfi", "cpsie i", "isb", options(nomem, nostack, preserves_flags));
+    }
+    // Interrupt handlers may have modified memory while the CPU was halted.
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Atomically enables *regular* interrupts and halts the current CPU
+/// until the next interrupt arrives, then disables interrupts again.
+///
+/// This is the same as [`enable_interrupts_and_halt()`], except that interrupts
+/// are disabled again after the interrupt that woke up the CPU has been handled.
+/// This is useful for idle loops that check for work with interrupts held.
+#[inline(always)]
+#[cfg(not(any(feature = "unix_signals", feature = "sim", loom)))]
+pub fn enable_interrupts_and_wait() {
+    compiler_fence(Ordering::SeqCst);
+    unsafe {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        asm!("sti; hlt; cli", options(nomem, nostack));
+
+        #[cfg(target_arch = "aarch64")]
+        asm!("wfi", "msr daifclr, #2", "isb", "msr daifset, #2", options(nomem, nostack, preserves_flags));
+
+        #[cfg(target_arch = "arm")]
+        asm!("wfi", "cpsie i", "isb", "cpsid i", options(nomem, nostack, preserves_flags));
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
 /// Unconditionally enables fast interrupts (FIQs); aarch64-only.
 ///
 /// On aarch64, NMIs are only available as a hardware extension,
 /// therefore we only deal with FIQs here, which are widely supported.
 #[inline(always)]
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(any(feature = "unix_signals", feature = "sim", loom))))]
 pub fn enable_fast_interrupts() {
     compiler_fence(Ordering::SeqCst);
     unsafe {
@@ -99,7 +320,7 @@ pub fn enable_fast_interrupts() {
 /// On aarch64, NMIs are only available as a hardware extension,
 /// therefore we only deal with FIQs here, which are widely supported.
 #[inline(always)]
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(any(feature = "unix_signals", feature = "sim", loom))))]
 pub fn disable_fast_interrupts() {
     unsafe {
         // Clear the F bit, which is bit 0 of the DAIF bitset.
@@ -113,6 +334,7 @@ pub fn disable_fast_interrupts() {
 /// This only checks whether *regular* interrupts are enabled,
 /// not NMIs or fast interrupts (FIQs on aarch64).
 #[inline(always)]
+#[cfg(not(any(feature = "unix_signals", feature = "sim", loom)))]
 pub fn interrupts_enabled() -> bool {
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     unsafe {
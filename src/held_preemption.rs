@@ -0,0 +1,64 @@
+use core::{fmt, sync::atomic::AtomicUsize};
+use crate::held_counter::{CounterHooks, HeldCounter};
+use crate::holding_mutex::Hold;
+
+/// A hook that lets this crate disable preemption on the current CPU.
+///
+/// This must be implemented by the OS and registered via [`set_preemption_controller()`].
+pub trait PreemptionController {
+    /// Returns the current CPU's preemption-disable counter.
+    ///
+    /// The OS must not preempt the current task while its CPU's counter is non-zero,
+    /// which also prevents the task from migrating to another CPU.
+    ///
+    /// This is invoked with interrupts held, so the current task cannot be preempted
+    /// or migrate to another CPU before the counter is incremented.
+    fn local_preempt_count() -> &'static AtomicUsize;
+
+    /// Invoked after the current CPU's counter drops back to zero,
+    /// e.g., to yield if a reschedule was requested in the meantime.
+    ///
+    /// The default implementation does nothing.
+    fn preemption_enabled() { }
+}
+
+static HOOKS: CounterHooks = CounterHooks::new();
+
+/// Registers `C` as the OS's [`PreemptionController`].
+///
+/// This replaces any previously-registered controller.
+/// Before a controller is registered, [`hold_preemption()`] falls back to holding
+/// interrupts, which also prevents preemption.
+pub fn set_preemption_controller<C: PreemptionController>() {
+    HOOKS.register(C::local_preempt_count, C::preemption_enabled);
+}
+
+/// A guard type for withholding preemption on the current CPU.
+///
+/// When dropped, the current CPU's preemption-disable counter is returned to its prior value,
+/// so preemption is only re-enabled once the outermost guard is dropped.
+/// Interrupts remain enabled while this guard is held.
+pub struct HeldPreemption(HeldCounter);
+
+/// Prevents the current task from being preempted
+/// until the returned `HeldPreemption` object is dropped.
+///
+/// Unlike [`hold_interrupts()`](crate::hold_interrupts), this leaves interrupts enabled.
+/// If no [`PreemptionController`] has been registered, this holds interrupts instead.
+pub fn hold_preemption() -> HeldPreemption {
+    HeldPreemption(HOOKS.hold())
+}
+
+impl Hold for HeldPreemption {
+    const NAME: &'static str = "PreemptSafe";
+
+    fn hold() -> HeldPreemption {
+        hold_preemption()
+    }
+}
+
+impl fmt::Debug for HeldPreemption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HeldPreemption {{ holding_interrupts: {} }}", self.0.is_holding_interrupts())
+    }
+}
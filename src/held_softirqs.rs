@@ -0,0 +1,65 @@
+use core::{fmt, sync::atomic::AtomicUsize};
+use crate::held_counter::{CounterHooks, HeldCounter};
+use crate::holding_mutex::Hold;
+
+/// A hook that lets this crate hold off deferred interrupt handlers
+/// (softirqs, bottom halves, tasklets, etc.) on the current CPU.
+///
+/// This must be implemented by the OS and registered via [`set_softirq_controller()`].
+pub trait SoftirqController {
+    /// Returns the current CPU's softirq-disable counter.
+    ///
+    /// The OS must not run deferred handlers on a CPU while its counter is non-zero,
+    /// and must not migrate a task to another CPU while it holds softirqs.
+    ///
+    /// This is invoked with interrupts held, so the current task cannot migrate
+    /// to another CPU before the counter is incremented.
+    fn local_softirq_count() -> &'static AtomicUsize;
+
+    /// Invoked after the current CPU's counter drops back to zero,
+    /// e.g., to run any deferred handlers that became pending in the meantime.
+    ///
+    /// The default implementation does nothing.
+    fn softirqs_enabled() { }
+}
+
+static HOOKS: CounterHooks = CounterHooks::new();
+
+/// Registers `C` as the OS's [`SoftirqController`].
+///
+/// This replaces any previously-registered controller.
+/// Before a controller is registered, [`hold_softirqs()`] falls back to holding
+/// interrupts, which also holds off deferred handlers.
+pub fn set_softirq_controller<C: SoftirqController>() {
+    HOOKS.register(C::local_softirq_count, C::softirqs_enabled);
+}
+
+/// A guard type for withholding deferred interrupt handlers (softirqs) on the current CPU.
+///
+/// When dropped, the current CPU's softirq-disable counter is returned to its prior value,
+/// so softirqs are only re-enabled once the outermost guard is dropped.
+/// Hardware interrupts remain enabled while this guard is held.
+pub struct HeldSoftirqs(HeldCounter);
+
+/// Prevents deferred interrupt handlers (softirqs) from running on the current CPU
+/// until the returned `HeldSoftirqs` object is dropped.
+///
+/// Unlike [`hold_interrupts()`](crate::hold_interrupts), this leaves hardware interrupts enabled.
+/// If no [`SoftirqController`] has been registered, this holds interrupts instead.
+pub fn hold_softirqs() -> HeldSoftirqs {
+    HeldSoftirqs(HOOKS.hold())
+}
+
+impl Hold for HeldSoftirqs {
+    const NAME: &'static str = "BhSafe";
+
+    fn hold() -> HeldSoftirqs {
+        hold_softirqs()
+    }
+}
+
+impl fmt::Debug for HeldSoftirqs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HeldSoftirqs {{ holding_interrupts: {} }}", self.0.is_holding_interrupts())
+    }
+}
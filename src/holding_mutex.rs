@@ -0,0 +1,241 @@
+use core::{fmt, marker::PhantomData, ops::{Deref, DerefMut}, ptr};
+use spin::{Mutex, MutexGuard};
+
+/// A guard that holds off some kind of concurrent execution on the current CPU,
+/// e.g., softirqs or preemption, until it is dropped.
+pub trait Hold {
+    /// The suffix of the names of the lock types that acquire this guard, e.g., `"PreemptSafe"`,
+    /// which they print in their `Debug` output.
+    const NAME: &'static str;
+
+    /// Starts holding, returning the guard.
+    fn hold() -> Self;
+}
+
+/// A spinlock based on [spin::Mutex] that acquires a [`Hold`] guard `H`
+/// for the duration of the lock being held.
+///
+/// This is the implementation of [`MutexBhSafe`](crate::MutexBhSafe)
+/// and [`MutexPreemptSafe`](crate::MutexPreemptSafe).
+pub struct HoldingMutex<T: ?Sized, H: Hold> {
+    _hold: PhantomData<fn() -> H>,
+    lock: Mutex<T>,
+}
+
+/// A guard to which the protected data can be accessed
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct HoldingMutexGuard<'a, T: ?Sized + 'a, H: Hold> {
+    // The lock this guard was obtained from, used to re-acquire it in `unlocked()`.
+    mutex: &'a HoldingMutex<T, H>,
+    // Whether the lock was forcibly taken from its previous holder.
+    broken: bool,
+    guard: MutexGuard<'a, T>,
+    // `_held` will be dropped after `guard`.
+    // Rust guarantees that fields are dropped in the order of declaration.
+    _held: H,
+}
+
+// Same unsafe impls as `std::sync::Mutex`
+unsafe impl<T: ?Sized + Send, H: Hold> Sync for HoldingMutex<T, H> {}
+unsafe impl<T: ?Sized + Send, H: Hold> Send for HoldingMutex<T, H> {}
+
+impl<T, H: Hold> HoldingMutex<T, H> {
+    /// Creates a new spinlock wrapping the supplied data.
+    pub const fn new(data: T) -> HoldingMutex<T, H> {
+        HoldingMutex {
+            _hold: PhantomData,
+            lock: Mutex::new(data),
+        }
+    }
+
+    /// Consumes this HoldingMutex, returning the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.lock.into_inner()
+    }
+}
+
+impl<T: ?Sized, H: Hold> HoldingMutex<T, H> {
+    /// Locks the spinlock and returns a guard.
+    ///
+    /// The returned value may be dereferenced for data access
+    /// and the lock will be dropped when the guard falls out of scope.
+    #[inline(always)]
+    pub fn lock(&self) -> HoldingMutexGuard<'_, T, H> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Locks the spinlock and invokes `f` with the protected data, returning its result.
+    #[inline]
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut *self.lock())
+    }
+
+    /// Attempts to lock the spinlock and, if successful, invokes `f` with the protected data.
+    #[inline]
+    pub fn try_with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        self.try_lock().map(|mut guard| f(&mut *guard))
+    }
+
+    /// Returns `true` if the lock is currently held.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+
+    /// Force unlock the spinlock.
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if the lock is not held by the current
+    /// thread. However, this can be useful in some instances for exposing the
+    /// lock to FFI that doesn't know how to deal with RAII.
+    ///
+    /// If the lock isn't held, this is a no-op.
+    pub unsafe fn force_unlock(&self) {
+        self.lock.force_unlock()
+    }
+
+    /// Tries to lock the HoldingMutex. If it is already locked, it will return None. Otherwise it returns
+    /// a guard within Some.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<HoldingMutexGuard<'_, T, H>> {
+        if self.lock.is_locked() { return None; }
+        let _held = H::hold();
+        self.lock.try_lock().map(|guard| HoldingMutexGuard {
+            mutex: self,
+            broken: false,
+            guard,
+            _held,
+        })
+    }
+
+    /// Locks the spinlock, forcibly taking it from its current holder
+    /// if it cannot be acquired within `spins` attempts.
+    ///
+    /// This is intended for panic handlers and crash-dump paths, where the lock
+    /// may be held by a CPU that will never release it, e.g., the current CPU.
+    /// Whether the lock was taken forcibly can be checked with [`HoldingMutexGuard::is_broken()`].
+    ///
+    /// The lock is broken after a single attempt if [`set_system_panicking()`](crate::set_system_panicking)
+    /// has been invoked.
+    ///
+    /// If the previous holder is still running, it may access the data concurrently,
+    /// and dropping its guard will release the lock out from under the new holder.
+    pub fn lock_or_break(&self, spins: usize) -> HoldingMutexGuard<'_, T, H> {
+        let spins = if crate::lock_break::is_system_panicking() { 0 } else { spins };
+
+        // Even without waiting, a free lock is taken normally rather than broken.
+        for _ in 0..spins.max(1) {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+        loop {
+            // SAFETY: the caller accepts that the previous holder is presumed to never release the lock.
+            unsafe { self.lock.force_unlock() };
+            if let Some(mut guard) = self.try_lock() {
+                guard.broken = true;
+                return guard;
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`HoldingMutex`] mutably, no actual locking needs to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.lock.get_mut()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, H: Hold> fmt::Debug for HoldingMutex<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.lock.try_lock() {
+            Some(guard) => write!(f, "Mutex{} {{ data: {:?} }}", H::NAME, &*guard),
+            None => write!(f, "Mutex{} {{ <locked> }}", H::NAME),
+        }
+    }
+}
+
+impl<T: Default, H: Hold> Default for HoldingMutex<T, H> {
+    fn default() -> HoldingMutex<T, H> {
+        HoldingMutex::new(Default::default())
+    }
+}
+
+impl<'a, T: ?Sized, H: Hold> HoldingMutexGuard<'a, T, H> {
+    /// Returns `true` if this guard was obtained by [`HoldingMutex::lock_or_break()`]
+    /// forcibly taking the lock from its previous holder.
+    ///
+    /// This is an associated function rather than a method,
+    /// to avoid conflicting with methods on the protected data.
+    pub fn is_broken(s: &Self) -> bool {
+        s.broken
+    }
+
+    /// Temporarily unlocks the mutex and drops its [`Hold`] guard
+    /// while invoking `f`, then re-acquires both before returning.
+    ///
+    /// This is an associated function rather than a method,
+    /// to avoid conflicting with methods on the protected data.
+    ///
+    /// If `f` panics, the guard cannot be restored, as re-acquiring the lock while unwinding
+    /// could deadlock or panic again, so this panics again instead, which aborts.
+    /// Panics are detected via the hook registered with [`set_panicking_hook()`](crate::set_panicking_hook);
+    /// without it, the lock is re-acquired while unwinding.
+    pub fn unlocked<R, F: FnOnce() -> R>(s: &mut Self, f: F) -> R {
+        /// Re-acquires the lock into `slot` when dropped, or aborts if unwinding.
+        struct Relock<'g, 'a, T: ?Sized, H: Hold> {
+            slot: &'g mut HoldingMutexGuard<'a, T, H>,
+            mutex: &'a HoldingMutex<T, H>,
+            broken: bool,
+        }
+        impl<'g, 'a, T: ?Sized, H: Hold> Drop for Relock<'g, 'a, T, H> {
+            fn drop(&mut self) {
+                if crate::poison::panicking() {
+                    // The empty `slot` would otherwise be dropped while unwinding.
+                    panic!("Mutex{}Guard::unlocked(): cannot re-acquire the lock while panicking", H::NAME);
+                }
+                let mut guard = self.mutex.lock();
+                guard.broken = self.broken;
+                unsafe { ptr::write(self.slot, guard); }
+            }
+        }
+
+        let mutex = s.mutex;
+        let broken = s.broken;
+        // SAFETY: the guard in `s` is dropped here and always rewritten by `Relock::drop()`
+        // before it can be accessed again, unless that aborts.
+        unsafe { ptr::drop_in_place(s); }
+        let _relock = Relock { slot: s, mutex, broken };
+        f()
+    }
+}
+
+impl<'a, T: ?Sized, H: Hold> Deref for HoldingMutexGuard<'a, T, H> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized, H: Hold> DerefMut for HoldingMutexGuard<'a, T, H> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
@@ -0,0 +1,243 @@
+use core::{fmt, marker::PhantomData, ops::{Deref, DerefMut}};
+use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::holding_mutex::Hold;
+
+/// A simple wrapper around a `RwLock` whose guards acquire a [`Hold`] guard `H`
+/// for the duration of the lock being held.
+///
+/// This is the implementation of [`RwLockPreemptSafe`](crate::RwLockPreemptSafe).
+pub struct HoldingRwLock<T: ?Sized, H: Hold> {
+    _hold: PhantomData<fn() -> H>,
+    rwlock: RwLock<T>,
+}
+
+/// A guard to which the protected data can be read
+///
+/// When the guard falls out of scope it will decrement the read count,
+/// potentially releasing the lock and potentially dropping its [`Hold`] guard.
+pub struct HoldingRwLockReadGuard<'a, T: 'a + ?Sized, H: Hold> {
+    guard: RwLockReadGuard<'a, T>,
+    // `_held` will be dropped after `guard`.
+    // Rust guarantees that fields are dropped in the order of declaration.
+    _held: H,
+}
+
+/// A guard to which the protected data can be written
+///
+/// When the guard falls out of scope it will release the lock and drop its [`Hold`] guard.
+pub struct HoldingRwLockWriteGuard<'a, T: 'a + ?Sized, H: Hold> {
+    guard: RwLockWriteGuard<'a, T>,
+    // `_held` will be dropped after `guard`.
+    // Rust guarantees that fields are dropped in the order of declaration.
+    _held: H,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send, H: Hold> Send for HoldingRwLock<T, H> {}
+unsafe impl<T: ?Sized + Send + Sync, H: Hold> Sync for HoldingRwLock<T, H> {}
+
+impl<T, H: Hold> HoldingRwLock<T, H> {
+    /// Creates a new spinlock wrapping the supplied data.
+    #[inline]
+    pub const fn new(data: T) -> HoldingRwLock<T, H> {
+        HoldingRwLock {
+            _hold: PhantomData,
+            rwlock: RwLock::new(data),
+        }
+    }
+
+    /// Consumes this `HoldingRwLock`, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.rwlock.into_inner()
+    }
+}
+
+impl<T: ?Sized, H: Hold> HoldingRwLock<T, H> {
+    /// Locks this rwlock with shared read access, blocking the current thread
+    /// until it can be acquired.
+    ///
+    /// Returns an RAII guard which will release this thread's shared access
+    /// once it is dropped, along with its [`Hold`] guard.
+    ///
+    /// ```no_run
+    /// let mylock = irq_safety::RwLockPreemptSafe::new(0);
+    /// {
+    ///     let data = mylock.read();
+    ///     // The lock is now locked, preemption is disabled, and the data can be read
+    ///     println!("{}", *data);
+    /// }
+    /// ```
+    #[inline]
+    pub fn read(&self) -> HoldingRwLockReadGuard<'_, T, H> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Attempt to acquire this lock with shared read access.
+    ///
+    /// This function will never block and will return immediately if `read`
+    /// would otherwise succeed. Returns `Some` of an RAII guard which will
+    /// release the shared access of this thread when dropped, or `None` if the
+    /// access could not be granted.
+    #[inline]
+    pub fn try_read(&self) -> Option<HoldingRwLockReadGuard<'_, T, H>> {
+        if self.rwlock.writer_count() > 0 { return None; }
+        let _held = H::hold();
+        self.rwlock.try_read().map(|guard| HoldingRwLockReadGuard {
+            guard,
+            _held,
+        })
+    }
+
+    /// Locks this rwlock with shared read access and invokes `f` with the protected data,
+    /// returning its result.
+    #[inline]
+    pub fn with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&*self.read())
+    }
+
+    /// Attempts to lock this rwlock with shared read access and,
+    /// if successful, invokes `f` with the protected data.
+    #[inline]
+    pub fn try_with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        self.try_read().map(|guard| f(&*guard))
+    }
+
+    /// Return the number of readers that currently hold the lock (including upgradable readers).
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    pub fn reader_count(&self) -> usize {
+        self.rwlock.reader_count()
+    }
+
+    /// Return the number of writers that currently hold the lock.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    pub fn writer_count(&self) -> usize {
+        self.rwlock.writer_count()
+    }
+
+    /// Force decrement the reader count.
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if there are outstanding read guards
+    /// live, or if called more times than `read` has been called, but can be
+    /// useful in FFI contexts where the caller doesn't know how to deal with
+    /// RAII.
+    pub unsafe fn force_read_decrement(&self) {
+        self.rwlock.force_read_decrement();
+    }
+
+    /// Force unlock exclusive write access.
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if there are outstanding write guards
+    /// live, or if called when there are current readers, but can be useful in
+    /// FFI contexts where the caller doesn't know how to deal with RAII.
+    pub unsafe fn force_write_unlock(&self) {
+        self.rwlock.force_write_unlock();
+    }
+
+    /// Lock this rwlock with exclusive write access, blocking the current
+    /// thread until it can be acquired.
+    ///
+    /// Returns an RAII guard which will drop the write access of this rwlock
+    /// when dropped, along with its [`Hold`] guard.
+    #[inline]
+    pub fn write(&self) -> HoldingRwLockWriteGuard<'_, T, H> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Attempt to lock this rwlock with exclusive write access.
+    ///
+    /// This function does not ever block, and it will return `None` if a call
+    /// to `write` would otherwise block. If successful, an RAII guard is
+    /// returned.
+    #[inline]
+    pub fn try_write(&self) -> Option<HoldingRwLockWriteGuard<'_, T, H>> {
+        if self.rwlock.writer_count() > 0 || self.rwlock.reader_count() > 0 {
+            return None;
+        }
+        let _held = H::hold();
+        self.rwlock.try_write().map(|guard| HoldingRwLockWriteGuard {
+            guard,
+            _held,
+        })
+    }
+
+    /// Locks this rwlock with exclusive write access and invokes `f` with the protected data,
+    /// returning its result.
+    #[inline]
+    pub fn with_write<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut *self.write())
+    }
+
+    /// Attempts to lock this rwlock with exclusive write access and,
+    /// if successful, invokes `f` with the protected data.
+    #[inline]
+    pub fn try_with_write<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        self.try_write().map(|mut guard| f(&mut *guard))
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`HoldingRwLock`] mutably, no actual locking needs to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.rwlock.get_mut()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, H: Hold> fmt::Debug for HoldingRwLock<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rwlock.try_read() {
+            Some(guard) => write!(f, "RwLock{} {{ data: {:?} }}", H::NAME, &*guard),
+            None => write!(f, "RwLock{} {{ <locked> }}", H::NAME),
+        }
+    }
+}
+
+impl<T: Default, H: Hold> Default for HoldingRwLock<T, H> {
+    fn default() -> HoldingRwLock<T, H> {
+        HoldingRwLock::new(Default::default())
+    }
+}
+
+impl<'rwlock, T: ?Sized, H: Hold> Deref for HoldingRwLockReadGuard<'rwlock, T, H> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'rwlock, T: ?Sized, H: Hold> Deref for HoldingRwLockWriteGuard<'rwlock, T, H> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'rwlock, T: ?Sized, H: Hold> DerefMut for HoldingRwLockWriteGuard<'rwlock, T, H> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
@@ -0,0 +1,110 @@
+//! A hook that runs deferred work when the outermost interrupt hold is released.
+//!
+//! Interrupt handlers often need to defer work (e.g., pending softirqs or a reschedule request)
+//! until interrupts are enabled again. An interrupt handler can mark such work as pending
+//! for a CPU via [`set_pending_work()`]; then, when the outermost [`HeldInterrupts`] guard
+//! on that CPU re-enables interrupts, the [`InterruptsRestoredHook`] registered via
+//! [`register_interrupts_restored_hook()`] is invoked.
+//!
+//! When no work is pending on any CPU, checking for it costs a single atomic load.
+//!
+//! [`HeldInterrupts`]: crate::HeldInterrupts
+
+use core::{
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+use crate::per_cpu::CpuId;
+
+/// The maximum number of CPUs that can have pending work.
+pub const MAX_CPUS: usize = 256;
+
+/// Deferred work that runs when the outermost interrupt hold on a CPU is released.
+///
+/// This must be implemented by the OS and registered via [`register_interrupts_restored_hook()`].
+pub trait InterruptsRestoredHook {
+    /// Runs the work that is pending on the current CPU.
+    ///
+    /// This is invoked with interrupts enabled, after the pending work flag
+    /// for the current CPU has been cleared. Work marked as pending while this
+    /// is running will be handled the next time the outermost guard is released.
+    fn interrupts_restored();
+}
+
+/// A registered hook along with the function that determines the current CPU,
+/// such that they are always registered together.
+struct Hooks {
+    hook: fn(),
+    current_cpu: fn() -> usize,
+}
+
+struct HooksFor<C, H>(PhantomData<fn() -> (C, H)>);
+
+impl<C: CpuId, H: InterruptsRestoredHook> HooksFor<C, H> {
+    const HOOKS: &'static Hooks = &Hooks {
+        hook: H::interrupts_restored,
+        current_cpu: C::current_cpu,
+    };
+}
+
+/// The registered hooks, or null if none have been registered.
+static HOOKS: AtomicPtr<Hooks> = AtomicPtr::new(ptr::null_mut());
+/// The number of CPUs that currently have pending work.
+static PENDING_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Whether each CPU currently has pending work.
+static PENDING: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// Registers `H` as the hook to be invoked when the outermost [`HeldInterrupts`](crate::HeldInterrupts)
+/// guard on a CPU with pending work re-enables interrupts.
+///
+/// `C` is used to determine the current CPU when work is pending.
+/// This replaces any previously-registered hook.
+pub fn register_interrupts_restored_hook<C: CpuId, H: InterruptsRestoredHook>() {
+    let hooks: &'static Hooks = HooksFor::<C, H>::HOOKS;
+    HOOKS.store(hooks as *const Hooks as *mut Hooks, Ordering::Release);
+}
+
+/// Marks the given `cpu` as having pending work, such that the registered hook
+/// will run the next time the outermost interrupt hold on that CPU is released.
+///
+/// This never blocks and may be invoked from an interrupt handler.
+///
+/// # Panics
+///
+/// Panics if `cpu` is not less than [`MAX_CPUS`].
+pub fn set_pending_work(cpu: usize) {
+    if !PENDING[cpu].swap(true, Ordering::AcqRel) {
+        PENDING_COUNT.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Returns whether the given `cpu` currently has pending work.
+pub fn has_pending_work(cpu: usize) -> bool {
+    PENDING.get(cpu).is_some_and(|pending| pending.load(Ordering::Acquire))
+}
+
+/// Invoked right before the outermost interrupt hold is released, while interrupts are still held.
+///
+/// If the current CPU has pending work, this clears its pending flag and returns the hook,
+/// which the caller must invoke after re-enabling interrupts.
+#[inline(always)]
+pub(crate) fn take_pending_hook() -> Option<fn()> {
+    if PENDING_COUNT.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    take_pending_hook_slow()
+}
+
+#[cold]
+fn take_pending_hook_slow() -> Option<fn()> {
+    // SAFETY: any non-null pointer was stored from a `&'static Hooks`.
+    let hooks = unsafe { HOOKS.load(Ordering::Acquire).as_ref() }?;
+    let pending = PENDING.get((hooks.current_cpu)())?;
+    if pending.swap(false, Ordering::AcqRel) {
+        PENDING_COUNT.fetch_sub(1, Ordering::Release);
+        Some(hooks.hook)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,282 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use crate::held_interrupts::hold_interrupts;
+
+/// A fixed-capacity, lock-free ring buffer for handing off items
+/// from an interrupt handler to a regular thread.
+///
+/// A channel is used by splitting it into a producer handle and a [`ThreadConsumer`] handle:
+/// * [`IrqChannel::split()`] returns a single [`IrqProducer`], which never spins,
+///   never blocks, and never touches the interrupt state,
+///   making it suitable for use directly within an interrupt handler.
+/// * [`IrqChannel::split_mpsc()`] returns a [`MpscIrqProducer`], which can be shared
+///   among multiple producers. Producers are serialized by a small spinlock,
+///   so interrupts are held while pushing to avoid deadlocking against
+///   an interrupt handler that pushes on the same CPU.
+///
+/// In both cases, the consumer side is lock-free and does not need to disable interrupts.
+///
+/// A channel can only be split once.
+///
+/// # Example
+///
+/// ```
+/// use irq_safety::IrqChannel;
+///
+/// static SCANCODES: IrqChannel<u8, 16> = IrqChannel::new();
+///
+/// let (mut producer, mut consumer) = SCANCODES.split().unwrap();
+/// assert!(SCANCODES.split().is_none());
+///
+/// // Typically invoked from the keyboard's interrupt handler.
+/// producer.push(0x1E).unwrap();
+/// producer.push(0x9E).unwrap();
+///
+/// assert_eq!(consumer.pop(), Some(0x1E));
+/// assert_eq!(consumer.pop(), Some(0x9E));
+/// assert_eq!(consumer.pop(), None);
+/// ```
+pub struct IrqChannel<T, const N: usize> {
+    buffer: UnsafeCell<MaybeUninit<[T; N]>>,
+    /// The total number of items ever popped; only written by the consumer.
+    head: AtomicUsize,
+    /// The total number of items ever pushed; only written by the (current) producer.
+    tail: AtomicUsize,
+    /// Whether this channel has already been split into its handles.
+    split: AtomicBool,
+    /// Serializes producers when this channel was split into a [`MpscIrqProducer`].
+    producer_lock: AtomicBool,
+}
+
+/// The single producer side of an [`IrqChannel`], designed for use in interrupt context.
+pub struct IrqProducer<'a, T, const N: usize> {
+    channel: &'a IrqChannel<T, N>,
+}
+
+/// The shareable producer side of an [`IrqChannel`], which supports multiple producers.
+///
+/// Interrupts are held only while an item is being pushed.
+pub struct MpscIrqProducer<'a, T, const N: usize> {
+    channel: &'a IrqChannel<T, N>,
+}
+
+/// The consumer side of an [`IrqChannel`], designed for use in a regular thread context.
+pub struct ThreadConsumer<'a, T, const N: usize> {
+    channel: &'a IrqChannel<T, N>,
+    // A consumer must stay on one thread at a time, but may be moved between them.
+    _not_sync: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for IrqChannel<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for IrqChannel<T, N> {}
+unsafe impl<'a, T: Send, const N: usize> Send for IrqProducer<'a, T, N> {}
+unsafe impl<'a, T: Send, const N: usize> Send for MpscIrqProducer<'a, T, N> {}
+unsafe impl<'a, T: Send, const N: usize> Sync for MpscIrqProducer<'a, T, N> {}
+unsafe impl<'a, T: Send, const N: usize> Send for ThreadConsumer<'a, T, N> {}
+
+impl<T, const N: usize> IrqChannel<T, N> {
+    /// The free-running `head` and `tail` counters wrap around at `usize::MAX`,
+    /// so they only map onto consistent slots if `N` divides the number of `usize` values.
+    const CAPACITY_IS_POWER_OF_TWO: () = assert!(N.is_power_of_two(), "IrqChannel capacity must be a power of two");
+
+    /// Creates a new empty channel that can hold up to `N` items.
+    ///
+    /// `N` must be a power of two; otherwise, this fails to compile.
+    pub const fn new() -> IrqChannel<T, N> {
+        let () = Self::CAPACITY_IS_POWER_OF_TWO;
+        IrqChannel {
+            buffer: UnsafeCell::new(MaybeUninit::uninit()),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+            producer_lock: AtomicBool::new(false),
+        }
+    }
+
+    /// Splits this channel into a single producer handle and its consumer handle.
+    ///
+    /// Returns `None` if this channel has already been split.
+    pub fn split(&self) -> Option<(IrqProducer<'_, T, N>, ThreadConsumer<'_, T, N>)> {
+        self.take_split().then_some((
+            IrqProducer { channel: self },
+            ThreadConsumer { channel: self, _not_sync: PhantomData },
+        ))
+    }
+
+    /// Splits this channel into a shareable multi-producer handle and its consumer handle.
+    ///
+    /// Returns `None` if this channel has already been split.
+    pub fn split_mpsc(&self) -> Option<(MpscIrqProducer<'_, T, N>, ThreadConsumer<'_, T, N>)> {
+        self.take_split().then_some((
+            MpscIrqProducer { channel: self },
+            ThreadConsumer { channel: self, _not_sync: PhantomData },
+        ))
+    }
+
+    /// Returns the maximum number of items this channel can hold.
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of items currently in this channel.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline]
+    pub fn len(&self) -> usize {
+        // `head` must be loaded first: it never passes `tail`, so the `tail` loaded afterwards
+        // is at least as new, even if the caller is neither the producer nor the consumer.
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Returns `true` if this channel currently holds no items.
+    ///
+    /// The same caveats as for [`IrqChannel::len()`] apply.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn take_split(&self) -> bool {
+        self.split
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    fn slot(&self, index: usize) -> *mut T {
+        unsafe { (self.buffer.get() as *mut T).add(index & (N - 1)) }
+    }
+
+    /// Pushes an item; the caller must guarantee there is only one concurrent producer.
+    fn push_exclusive(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= N {
+            return Err(value);
+        }
+        unsafe { self.slot(tail).write(value); }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Pops an item; the caller must guarantee there is only one concurrent consumer.
+    fn pop_exclusive(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { self.slot(head).read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Drop for IrqChannel<T, N> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        let mut index = head;
+        while index != tail {
+            unsafe { ptr::drop_in_place(self.slot(index)); }
+            index = index.wrapping_add(1);
+        }
+    }
+}
+
+impl<T, const N: usize> Default for IrqChannel<T, N> {
+    fn default() -> IrqChannel<T, N> {
+        IrqChannel::new()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for IrqChannel<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IrqChannel {{ len: {}, capacity: {} }}", self.len(), N)
+    }
+}
+
+impl<'a, T, const N: usize> IrqProducer<'a, T, N> {
+    /// Pushes `value` into the channel.
+    ///
+    /// This never blocks and does not touch the interrupt state.
+    /// If the channel is full, `value` is returned back in an `Err`.
+    #[inline]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        self.channel.push_exclusive(value)
+    }
+
+    /// Returns `true` if the channel is currently full.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.channel.len() >= N
+    }
+}
+
+impl<'a, T, const N: usize> MpscIrqProducer<'a, T, N> {
+    /// Pushes `value` into the channel, holding interrupts while doing so.
+    ///
+    /// This spins only while another producer is in the middle of pushing.
+    /// If the channel is full, `value` is returned back in an `Err`.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let _held_irq = hold_interrupts();
+        while self.channel.producer_lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        let result = self.channel.push_exclusive(value);
+        self.channel.producer_lock.store(false, Ordering::Release);
+        result
+    }
+
+    /// Returns `true` if the channel is currently full.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.channel.len() >= N
+    }
+}
+
+impl<'a, T, const N: usize> Clone for MpscIrqProducer<'a, T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, const N: usize> Copy for MpscIrqProducer<'a, T, N> {}
+
+impl<'a, T, const N: usize> ThreadConsumer<'a, T, N> {
+    /// Removes and returns the oldest item in the channel, if any.
+    ///
+    /// This never blocks and does not need to disable interrupts.
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        self.channel.pop_exclusive()
+    }
+
+    /// Returns the number of items currently in the channel.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns `true` if the channel currently holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
+}
@@ -0,0 +1,142 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt,
+};
+#[cfg(feature = "allocator_api")]
+use core::{
+    alloc::{AllocError, Allocator},
+    ptr::{self, NonNull},
+};
+use crate::mutex_irqsafe::{MutexIrqSafe, MutexIrqSafeGuard};
+
+/// A heap allocator that requires exclusive access, e.g., a linked-list or buddy allocator.
+///
+/// This is wrapped by [`IrqSafeAllocator`] to make it usable as a global allocator.
+///
+/// # Safety
+///
+/// Implementations must uphold the same contract as [`GlobalAlloc`]:
+/// a non-null block returned by `alloc()` must be valid for `layout`
+/// and must not overlap any other live block.
+pub unsafe trait InnerAllocator {
+    /// Allocates a block of memory described by `layout`,
+    /// or returns a null pointer if it cannot be allocated.
+    fn alloc(&mut self, layout: Layout) -> *mut u8;
+
+    /// Deallocates the block of memory at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc()` on this allocator with the same `layout`,
+    /// and must not have been deallocated since.
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
+}
+
+/// A wrapper that makes an [`InnerAllocator`] usable as a [`GlobalAlloc`],
+/// holding interrupts across each allocation and deallocation.
+/// With the `allocator_api` feature enabled, it also implements
+/// the unstable [`Allocator`](core::alloc::Allocator) trait.
+///
+/// Because interrupts are held while the inner allocator is locked,
+/// an interrupt handler that allocates cannot deadlock with an allocation
+/// that it interrupted on the same CPU.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::alloc::Layout;
+/// use irq_safety::{InnerAllocator, IrqSafeAllocator};
+///
+/// struct Heap { /* ... */ }
+/// unsafe impl InnerAllocator for Heap {
+///     fn alloc(&mut self, layout: Layout) -> *mut u8 { core::ptr::null_mut() }
+///     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) { }
+/// }
+///
+/// #[global_allocator]
+/// static ALLOCATOR: IrqSafeAllocator<Heap> = IrqSafeAllocator::new(Heap { });
+/// ```
+pub struct IrqSafeAllocator<A> {
+    inner: MutexIrqSafe<A>,
+}
+
+impl<A> IrqSafeAllocator<A> {
+    /// Wraps the given inner allocator.
+    #[cfg(not(loom))]
+    pub const fn new(inner: A) -> IrqSafeAllocator<A> {
+        IrqSafeAllocator { inner: MutexIrqSafe::new(inner) }
+    }
+
+    /// Wraps the given inner allocator.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[cfg(loom)]
+    pub fn new(inner: A) -> IrqSafeAllocator<A> {
+        IrqSafeAllocator { inner: MutexIrqSafe::new(inner) }
+    }
+
+    /// Locks the inner allocator, e.g., to initialize it or give it more memory.
+    ///
+    /// Interrupts are held until the returned guard is dropped.
+    /// The global allocator must not be used while the guard is held.
+    #[cfg(not(feature = "sim"))]
+    #[inline]
+    pub fn lock(&self) -> MutexIrqSafeGuard<'_, A> {
+        self.inner.lock()
+    }
+
+    /// Locks the inner allocator, e.g., to initialize it or give it more memory.
+    ///
+    /// Interrupts are held until the returned guard is dropped.
+    /// The global allocator must not be used while the guard is held.
+    ///
+    /// Under `sim`, the guard is not tracked for deadlock reports,
+    /// as that would allocate while the inner allocator is locked.
+    #[cfg(feature = "sim")]
+    #[inline]
+    pub fn lock(&self) -> MutexIrqSafeGuard<'_, A> {
+        crate::sim::untracked(|| self.inner.lock())
+    }
+
+    /// Consumes this `IrqSafeAllocator`, returning the inner allocator.
+    pub fn into_inner(self) -> A {
+        self.inner.into_inner()
+    }
+}
+
+unsafe impl<A: InnerAllocator> GlobalAlloc for IrqSafeAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().dealloc(ptr, layout)
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: InnerAllocator> Allocator for IrqSafeAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Zero-sized allocations don't need any memory from the inner allocator.
+        let ptr = if layout.size() == 0 {
+            ptr::without_provenance_mut(layout.align())
+        } else {
+            self.lock().alloc(layout)
+        };
+        NonNull::new(ptr)
+            .map(|ptr| NonNull::slice_from_raw_parts(ptr, layout.size()))
+            .ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.lock().dealloc(ptr.as_ptr(), layout)
+        }
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for IrqSafeAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IrqSafeAllocator {{ inner: {:?} }}", self.inner)
+    }
+}
@@ -0,0 +1,111 @@
+use core::{fmt, hint::spin_loop};
+use crate::held_interrupts::hold_interrupts;
+use crate::mutex_irqsafe::MutexIrqSafe;
+
+/// How many times [`IrqSafeWriter::force_write()`] tries to lock the console
+/// before writing to it without the lock.
+const FORCE_WRITE_ATTEMPTS: usize = 1 << 20;
+
+/// A [`fmt::Write`] implementation for a console protected by a [`MutexIrqSafe`].
+///
+/// Each formatted write holds the console lock (and thus interrupts) for its entire duration,
+/// so messages from different CPUs and interrupt handlers are never interleaved.
+///
+/// With the `log` feature enabled, this also implements [`log::Log`],
+/// such that it can be registered as the global logger.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::fmt::Write;
+/// use irq_safety::{IrqSafeWriter, MutexIrqSafe};
+///
+/// struct SerialPort;
+/// impl Write for SerialPort {
+///     fn write_str(&mut self, s: &str) -> core::fmt::Result { Ok(()) }
+/// }
+///
+/// static CONSOLE: MutexIrqSafe<SerialPort> = MutexIrqSafe::new(SerialPort);
+///
+/// writeln!(IrqSafeWriter::new(&CONSOLE), "Hello, {}!", "world").unwrap();
+/// ```
+pub struct IrqSafeWriter<'a, W: fmt::Write> {
+    console: &'a MutexIrqSafe<W>,
+}
+
+impl<'a, W: fmt::Write> IrqSafeWriter<'a, W> {
+    /// Creates a writer for the given console.
+    pub const fn new(console: &'a MutexIrqSafe<W>) -> IrqSafeWriter<'a, W> {
+        IrqSafeWriter { console }
+    }
+
+    /// Writes the formatted `args` to the console, even if its lock cannot be acquired,
+    /// e.g., from a panic handler.
+    ///
+    /// This tries to lock the console for a bounded number of attempts.
+    /// If that fails, the lock is presumably held by a CPU that will never release it,
+    /// e.g., the current CPU, which panicked while writing to the console,
+    /// so this writes to the console without the lock, with interrupts held.
+    /// With the `deadlock_detection` feature enabled, this skips the attempts
+    /// if the current CPU is known to hold the lock.
+    ///
+    /// The output may thus be interleaved with, or corrupt, another write in progress,
+    /// so this should only be used when the system cannot continue anyway.
+    pub fn force_write(&self, args: fmt::Arguments) -> fmt::Result {
+        #[cfg(feature = "deadlock_detection")]
+        let attempts = if self.console.held_by_current_cpu() { 0 } else { FORCE_WRITE_ATTEMPTS };
+        #[cfg(not(feature = "deadlock_detection"))]
+        let attempts = FORCE_WRITE_ATTEMPTS;
+
+        for _ in 0..attempts {
+            if let Some(mut console) = self.console.try_lock() {
+                return console.write_fmt(args);
+            }
+            spin_loop();
+        }
+        let _held_irq = hold_interrupts();
+        // SAFETY: the lock holder is presumed to never access the console again.
+        unsafe { (*self.console.as_mut_ptr()).write_fmt(args) }
+    }
+}
+
+impl<'a, W: fmt::Write> Clone for IrqSafeWriter<'a, W> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, W: fmt::Write> Copy for IrqSafeWriter<'a, W> {}
+
+impl<'a, W: fmt::Write> fmt::Write for IrqSafeWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.console.lock().write_str(s)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.console.lock().write_char(c)
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.console.lock().write_fmt(args)
+    }
+}
+
+impl<'a, W: fmt::Write> fmt::Debug for IrqSafeWriter<'a, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IrqSafeWriter {{ console: {:p} }}", self.console)
+    }
+}
+
+#[cfg(feature = "log")]
+impl<'a, W: fmt::Write + Send> log::Log for IrqSafeWriter<'a, W> {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let _ = writeln!(self.console.lock(), "[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) { }
+}
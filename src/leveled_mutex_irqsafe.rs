@@ -0,0 +1,235 @@
+//! Compile-time lock ordering for [`MutexIrqSafe`], via type-level lock levels.
+//!
+//! Each [`LeveledMutexIrqSafe`] belongs to a [`Level`], and can only be locked with a
+//! [`LevelToken`] for a lower level, i.e., one that its level implements [`LockAfter`] for.
+//! Locking it mutably borrows that token, and returns a token for its own level
+//! that remains valid until the guard is dropped,
+//! so locks can only be acquired in increasing order of their levels.
+//! Acquiring them in the wrong order, which could deadlock, fails to compile.
+
+use core::{fmt, marker::PhantomData};
+use crate::mutex_irqsafe::{MutexIrqSafe, MutexIrqSafeGuard};
+
+/// A level in a lock hierarchy.
+///
+/// Levels are usually uninhabited marker types declared with [`lock_levels!`](crate::lock_levels).
+pub trait Level {}
+
+/// Implemented by a [`Level`] whose locks may be acquired while holding a lock at level `L`.
+///
+/// To keep the hierarchy acyclic, this should be implemented for every pair of levels in order,
+/// which [`lock_levels!`](crate::lock_levels) does automatically.
+pub trait LockAfter<L: Level>: Level {}
+
+/// The lowest level, at which a context does not hold any leveled locks.
+pub enum Unlocked {}
+
+impl Level for Unlocked {}
+
+/// Implements [`Level`] for each of the given types, and [`LockAfter`] for each pair of them,
+/// such that locks at each level may be acquired after locks at all previous levels.
+///
+/// The first level must already implement [`Level`], e.g., [`Unlocked`].
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::{lock_levels, Unlocked};
+///
+/// pub enum Scheduler {}
+/// pub enum RunQueue {}
+/// pub enum Task {}
+///
+/// lock_levels!(Unlocked => Scheduler => RunQueue => Task);
+/// ```
+#[macro_export]
+macro_rules! lock_levels {
+    (@after $lower:ty $(=> $higher:ty)+) => {
+        $( impl $crate::LockAfter<$lower> for $higher {} )+
+        $crate::lock_levels!(@after $($higher)=>+);
+    };
+    (@after $last:ty) => {};
+    ($first:ty $(=> $rest:ty)+) => {
+        $( impl $crate::Level for $rest {} )+
+        $crate::lock_levels!(@after $first $(=> $rest)+);
+    };
+}
+
+/// A proof that the current context holds no leveled locks at or above level `L`.
+///
+/// A context, e.g., a thread or an interrupt handler, starts with a single token for [`Unlocked`]
+/// created by [`LevelToken::new()`],
+/// from which tokens for higher levels are obtained by locking a [`LeveledMutexIrqSafe`].
+/// Such tokens borrow the lower token that they were obtained from,
+/// so the lower token cannot be used again until they and their guard are dropped.
+pub struct LevelToken<'a, L: Level> {
+    _level: PhantomData<fn() -> L>,
+    _borrow: PhantomData<&'a mut ()>,
+}
+
+impl LevelToken<'static, Unlocked> {
+    /// Creates a token for a context that holds no leveled locks.
+    ///
+    /// # Safety
+    ///
+    /// This must only be invoked once at the entry of each context, e.g.,
+    /// when a thread starts or an interrupt handler is invoked,
+    /// and the token must not be moved into another context.
+    /// Locking with multiple root tokens in the same context defeats the lock ordering,
+    /// and so could deadlock.
+    pub const unsafe fn new() -> LevelToken<'static, Unlocked> {
+        LevelToken { _level: PhantomData, _borrow: PhantomData }
+    }
+}
+
+impl<'a, L: Level> LevelToken<'a, L> {
+    const fn next<'b, N: Level>(&'b mut self) -> LevelToken<'b, N> {
+        LevelToken { _level: PhantomData, _borrow: PhantomData }
+    }
+}
+
+impl<'a, L: Level> fmt::Debug for LevelToken<'a, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LevelToken<{}>", core::any::type_name::<L>())
+    }
+}
+
+/// A [`MutexIrqSafe`] at lock level `L`, which can only be locked with a [`LevelToken`]
+/// for a lower level, such that lock ordering is enforced at compile time.
+///
+/// Interrupts are held while the lock is held, exactly as with [`MutexIrqSafe`].
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::{lock_levels, LevelToken, LeveledMutexIrqSafe, Unlocked};
+///
+/// pub enum Scheduler {}
+/// pub enum RunQueue {}
+/// pub enum Task {}
+/// lock_levels!(Unlocked => Scheduler => RunQueue => Task);
+///
+/// static SCHEDULER: LeveledMutexIrqSafe<u32, Scheduler> = LeveledMutexIrqSafe::new(0);
+/// static RUNQUEUE: LeveledMutexIrqSafe<u32, RunQueue> = LeveledMutexIrqSafe::new(0);
+/// static TASK: LeveledMutexIrqSafe<u32, Task> = LeveledMutexIrqSafe::new(0);
+///
+/// // SAFETY: this is the only root token created in this context.
+/// let mut token = unsafe { LevelToken::new() };
+/// let (mut scheduler, mut token) = SCHEDULER.lock(&mut token);
+/// let (mut runqueue, mut token) = RUNQUEUE.lock(&mut token);
+/// let (mut task, _) = TASK.lock(&mut token);
+/// *task += *runqueue + *scheduler;
+/// ```
+///
+/// Acquiring the locks in the wrong order fails to compile:
+///
+/// ```compile_fail
+/// # use irq_safety::{lock_levels, LevelToken, LeveledMutexIrqSafe, Unlocked};
+/// # pub enum Scheduler {}
+/// # pub enum RunQueue {}
+/// # lock_levels!(Unlocked => Scheduler => RunQueue);
+/// # static SCHEDULER: LeveledMutexIrqSafe<u32, Scheduler> = LeveledMutexIrqSafe::new(0);
+/// # static RUNQUEUE: LeveledMutexIrqSafe<u32, RunQueue> = LeveledMutexIrqSafe::new(0);
+/// let mut token = unsafe { LevelToken::new() };
+/// let (runqueue, mut token) = RUNQUEUE.lock(&mut token);
+/// let (scheduler, _) = SCHEDULER.lock(&mut token);
+/// ```
+pub struct LeveledMutexIrqSafe<T: ?Sized, L: Level> {
+    _level: PhantomData<fn() -> L>,
+    lock: MutexIrqSafe<T>,
+}
+
+impl<T, L: Level> LeveledMutexIrqSafe<T, L> {
+    /// Creates a new spinlock at level `L` wrapping the supplied data.
+    #[cfg(not(loom))]
+    pub const fn new(data: T) -> LeveledMutexIrqSafe<T, L> {
+        LeveledMutexIrqSafe {
+            _level: PhantomData,
+            lock: MutexIrqSafe::new(data),
+        }
+    }
+
+    /// Creates a new spinlock at level `L` wrapping the supplied data.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[cfg(loom)]
+    pub fn new(data: T) -> LeveledMutexIrqSafe<T, L> {
+        LeveledMutexIrqSafe {
+            _level: PhantomData,
+            lock: MutexIrqSafe::new(data),
+        }
+    }
+
+    /// Consumes this `LeveledMutexIrqSafe`, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.lock.into_inner()
+    }
+}
+
+impl<T: ?Sized, L: Level> LeveledMutexIrqSafe<T, L> {
+    /// Locks the spinlock using a `token` for a lower level,
+    /// and returns a guard along with a token for level `L`.
+    ///
+    /// The `token` remains borrowed until both the guard and the returned token are dropped.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn lock<'a, P: Level>(
+        &'a self,
+        token: &'a mut LevelToken<'_, P>,
+    ) -> (MutexIrqSafeGuard<'a, T>, LevelToken<'a, L>)
+    where
+        L: LockAfter<P>,
+    {
+        (self.lock.lock(), token.next())
+    }
+
+    /// Tries to lock the spinlock using a `token` for a lower level, without spinning.
+    ///
+    /// If it is already locked, this returns `None`.
+    /// Otherwise, this returns a guard along with a token for level `L`.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_lock<'a, P: Level>(
+        &'a self,
+        token: &'a mut LevelToken<'_, P>,
+    ) -> Option<(MutexIrqSafeGuard<'a, T>, LevelToken<'a, L>)>
+    where
+        L: LockAfter<P>,
+    {
+        self.lock.try_lock().map(move |guard| (guard, token.next()))
+    }
+
+    /// Returns `true` if the lock is currently held.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the lock mutably, no actual locking or token needs to be used.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.lock.get_mut()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, L: Level> fmt::Debug for LeveledMutexIrqSafe<T, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.lock.try_lock() {
+            Some(guard) => write!(f, "LeveledMutexIrqSafe {{ data: {:?} }}", &*guard),
+            None => write!(f, "LeveledMutexIrqSafe {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default, L: Level> Default for LeveledMutexIrqSafe<T, L> {
+    fn default() -> LeveledMutexIrqSafe<T, L> {
+        LeveledMutexIrqSafe::new(Default::default())
+    }
+}
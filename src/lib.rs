@@ -7,17 +7,158 @@
 //! * [ MutexIrqSafe`] and [`RwLockIrqSafe`]: spinlock wrappers that use [`spin::Mutex`]
 //!   and [`spin::RwLock`] internally to auto-disable interrupts for the duration of 
 //!   the lock being held.
+//! * [`BrLockIrqSafe`]: an irq-safe "big reader" lock with per-CPU reader counts,
+//!   for data that is read often on all CPUs but rarely written.
+//! * [`ReentrantMutexIrqSafe`]: an irq-safe mutex that the owning CPU can re-acquire,
+//!   giving shared access to the data.
+//! * [`LeveledMutexIrqSafe`]: an irq-safe mutex at a type-level lock [`Level`],
+//!   which can only be locked with a [`LevelToken`] for a lower level,
+//!   such that lock ordering is enforced at compile time; see [`lock_levels!`].
+//! * [`CondvarIrqSafe`]: a condition variable for use with [`MutexIrqSafe`]
+//!   that can be notified from an interrupt handler.
+//! * [`SemaphoreIrqSafe`]: a counting semaphore whose permits can be released
+//!   from an interrupt handler without blocking.
+//! * [`IrqChannel`]: a lock-free ring buffer for handing off items from an
+//!   interrupt handler to a regular thread.
+//! * [`LocalIrqCell`]: a `RefCell`-like container for CPU-local data that only
+//!   holds interrupts, without any spinning.
+//! * [`PerCpu`]: a container with one instance per CPU, accessed with interrupts held.
+//! * `RcuCell` (with the `alloc` feature): a read-copy-update cell whose readers hold interrupts,
+//!   and whose old values are reclaimed once every CPU has reported a quiescent state
+//!   via `rcu_quiescent_state()`, after the OS has invoked `set_rcu_cpu_count()`.
+//! * [`IrqSafeAllocator`]: a [`GlobalAlloc`](core::alloc::GlobalAlloc) wrapper around an
+//!   [`InnerAllocator`] that holds interrupts across each allocation.
+//! * [`IrqSafeWriter`]: a [`fmt::Write`](core::fmt::Write) console writer over a [`MutexIrqSafe`],
+//!   with a panic-safe [`force_write()`](IrqSafeWriter::force_write) path and optional `log` support.
+//! * [`PoisoningMutexIrqSafe`] and [`PoisoningRwLockIrqSafe`]: opt-in variants of
+//!   [`MutexIrqSafe`] and [`RwLockIrqSafe`] that are poisoned by a panic while locked,
+//!   as detected by the hook registered with [`set_panicking_hook()`].
+//! * [`set_system_panicking()`]: makes [`MutexIrqSafe::lock_or_break()`] and
+//!   [`RwLockIrqSafe::write_or_break()`] forcibly take held locks immediately, for crash handlers.
+//! * [`register_interrupts_restored_hook()`]: runs deferred work, marked as pending by
+//!   [`set_pending_work()`], when the outermost [`HeldInterrupts`] guard re-enables interrupts.
+//! * [`HeldSoftirqs`] and [`MutexBhSafe`]: like [`HeldInterrupts`] and [`MutexIrqSafe`],
+//!   but only holding off deferred interrupt handlers (softirqs) via a [`SoftirqController`],
+//!   leaving hardware interrupts enabled.
+//! * [`HeldPreemption`], [`MutexPreemptSafe`] and [`RwLockPreemptSafe`]: the same,
+//!   but only disabling preemption via a [`PreemptionController`], leaving interrupts enabled.
+//!   These are instances of the generic [`HoldingMutex`] and [`HoldingRwLock`].
+//!
+//! # Unix signals as interrupts
+//! With the `unix_signals` feature enabled, this crate can be used in hosted
+//! Unix environments, where holding interrupts instead blocks a set of signals
+//! for the current thread; see `set_masked_signals()`.
+//! This allows the irq-safe locks to protect data shared with signal handlers.
+//!
+//! # Self-deadlock detection
+//! With the `deadlock_detection` feature enabled, [`MutexIrqSafe`] and [`RwLockIrqSafe`]
+//! record which CPU holds them exclusively, and panic with the lock's address and
+//! acquisition location when that CPU tries to acquire them again,
+//! rather than spinning forever with interrupts disabled;
+//! see [`set_deadlock_detection_cpu_id()`].
+//!
+//! # Lock registry
+//! With the `lock_registry` feature enabled, `'static` [`MutexIrqSafe`] and [`RwLockIrqSafe`]
+//! instances can be given a name via `register()`, which links them into a lock-free global list,
+//! and `dump_held_locks()` writes the name, state and owner of each one that is currently held,
+//! e.g., from a watchdog or panic handler after a hang.
+//!
+//! # Testing with simulated interrupts
+//! With the `sim` feature enabled, interrupts are simulated per thread,
+//! and test code can inject fake interrupt handlers at every point where
+//! interrupts are enabled; see the [`sim`] module.
+//!
+//! The lock types can also be model-checked with [`loom`](https://docs.rs/loom)
+//! by building with `RUSTFLAGS="--cfg loom"`, which swaps their atomics
+//! and the interrupt flag for loom-instrumented versions.
+//! Note that their `new()` functions are not `const` in that configuration.
 
 #![feature(negative_impls)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 
 #![no_std]
 
+#[cfg(any(feature = "sim", loom))]
+extern crate std;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(all(loom, any(feature = "sim", feature = "unix_signals")))]
+compile_error!("the `loom` cfg cannot be combined with the `sim` or `unix_signals` features");
+
 extern crate spin;
 
 pub use mutex_irqsafe::*;
 pub use rwlock_irqsafe::*;
 pub use held_interrupts::*;
+pub use condvar_irqsafe::*;
+pub use semaphore_irqsafe::*;
+pub use irq_channel::*;
+pub use local_irq_cell::*;
+pub use per_cpu::*;
+pub use held_softirqs::*;
+pub use holding_mutex::*;
+pub use holding_rwlock::*;
+pub use mutex_bhsafe::*;
+pub use held_preemption::*;
+pub use mutex_preemptsafe::*;
+pub use rwlock_preemptsafe::*;
+pub use reentrant_mutex_irqsafe::*;
+pub use brlock_irqsafe::*;
+pub use leveled_mutex_irqsafe::*;
+#[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+pub use signal_mask::set_masked_signals;
+#[cfg(feature = "alloc")]
+pub use rcu_cell::*;
+pub use irq_safe_allocator::*;
+pub use irq_safe_writer::*;
+pub use poison::{PoisonError, TryLockError, LockResult, TryLockResult, set_panicking_hook};
+pub use poisoning_mutex_irqsafe::*;
+pub use poisoning_rwlock_irqsafe::*;
+pub use lock_break::{set_system_panicking, is_system_panicking};
+#[cfg(feature = "deadlock_detection")]
+pub use deadlock_detection::set_deadlock_detection_cpu_id;
+#[cfg(feature = "lock_registry")]
+pub use lock_registry::dump_held_locks;
+pub use interrupts_restored::{
+    InterruptsRestoredHook, register_interrupts_restored_hook, set_pending_work, has_pending_work, MAX_CPUS,
+};
 
 mod mutex_irqsafe;
 mod rwlock_irqsafe;
 mod held_interrupts;
+mod condvar_irqsafe;
+mod semaphore_irqsafe;
+mod irq_channel;
+mod local_irq_cell;
+mod per_cpu;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(loom)]
+mod loom_backend;
+#[cfg(feature = "deadlock_detection")]
+mod deadlock_detection;
+#[cfg(feature = "lock_registry")]
+mod lock_registry;
+#[cfg(feature = "alloc")]
+mod rcu_cell;
+#[cfg(all(feature = "unix_signals", not(feature = "sim")))]
+mod signal_mask;
+mod interrupts_restored;
+mod held_counter;
+mod holding_mutex;
+mod holding_rwlock;
+mod held_softirqs;
+mod mutex_bhsafe;
+mod held_preemption;
+mod mutex_preemptsafe;
+mod rwlock_preemptsafe;
+mod reentrant_mutex_irqsafe;
+mod brlock_irqsafe;
+mod leveled_mutex_irqsafe;
+mod irq_safe_allocator;
+mod irq_safe_writer;
+mod lock_break;
+mod poison;
+mod poisoning_mutex_irqsafe;
+mod poisoning_rwlock_irqsafe;
@@ -0,0 +1,151 @@
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    ops::{Deref, DerefMut},
+};
+use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
+
+/// A `RefCell`-like container for data that is only ever accessed from a single CPU,
+/// such as per-CPU data.
+///
+/// Because such data is never shared with other CPUs, no spinning is needed;
+/// the only thing that can race with an access is an interrupt handler on the same CPU.
+/// Thus, [`LocalIrqCell::borrow_mut()`] simply holds interrupts for the duration
+/// of the borrow, which is much cheaper than acquiring a [`MutexIrqSafe`](crate::MutexIrqSafe).
+///
+/// If the cell is borrowed again while already borrowed on the current CPU
+/// (e.g., from an NMI handler, or from code that re-enabled interrupts
+/// while the borrow was live), this panics instead of deadlocking.
+///
+/// This type is `!Sync`; it is intended to be placed in CPU-local storage.
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::LocalIrqCell;
+///
+/// let counter = LocalIrqCell::new(0usize);
+/// {
+///     let mut count = counter.borrow_mut();
+///     // Interrupts are now disabled on this CPU.
+///     *count += 1;
+///     // Interrupts are restored to their prior state.
+/// }
+/// assert!(counter.try_borrow_mut().is_some());
+/// ```
+pub struct LocalIrqCell<T: ?Sized> {
+    borrowed: Cell<bool>,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides exclusive access to the contents of a [`LocalIrqCell`].
+///
+/// When the guard falls out of scope, the borrow is released
+/// and interrupts are restored to their prior state.
+pub struct LocalIrqCellGuard<'a, T: ?Sized + 'a> {
+    cell: &'a LocalIrqCell<T>,
+    // `_held_irq` will be dropped after the borrow is released in `drop()`.
+    _held_irq: HeldInterrupts,
+}
+
+unsafe impl<T: ?Sized + Send> Send for LocalIrqCell<T> {}
+
+impl<T> LocalIrqCell<T> {
+    /// Creates a new `LocalIrqCell` containing the given `data`.
+    pub const fn new(data: T) -> LocalIrqCell<T> {
+        LocalIrqCell {
+            borrowed: Cell::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this `LocalIrqCell`, returning the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> LocalIrqCell<T> {
+    /// Mutably borrows the contained data, holding interrupts until the returned guard is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is already borrowed on this CPU.
+    #[inline]
+    #[track_caller]
+    pub fn borrow_mut(&self) -> LocalIrqCellGuard<'_, T> {
+        match self.try_borrow_mut() {
+            Some(guard) => guard,
+            None => panic!("LocalIrqCell at {:p} is already borrowed on this CPU", self),
+        }
+    }
+
+    /// Attempts to mutably borrow the contained data,
+    /// holding interrupts until the returned guard is dropped.
+    ///
+    /// Returns `None` if the data is already borrowed on this CPU.
+    #[inline]
+    pub fn try_borrow_mut(&self) -> Option<LocalIrqCellGuard<'_, T>> {
+        // Interrupts must be held *before* checking the borrow flag,
+        // otherwise an interrupt handler could borrow the data in between.
+        let _held_irq = hold_interrupts();
+        if self.borrowed.replace(true) {
+            return None;
+        }
+        Some(LocalIrqCellGuard {
+            cell: self,
+            _held_irq,
+        })
+    }
+
+    /// Returns `true` if the data is currently borrowed.
+    #[inline]
+    pub fn is_borrowed(&self) -> bool {
+        self.borrowed.get()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`LocalIrqCell`] mutably,
+    /// no interrupts need to be held and no borrow needs to be tracked.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for LocalIrqCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_borrow_mut() {
+            Some(guard) => write!(f, "LocalIrqCell {{ data: {:?} }}", &*guard),
+            None => write!(f, "LocalIrqCell {{ <borrowed> }}"),
+        }
+    }
+}
+
+impl<T: Default> Default for LocalIrqCell<T> {
+    fn default() -> LocalIrqCell<T> {
+        LocalIrqCell::new(Default::default())
+    }
+}
+
+impl<'a, T: ?Sized> Drop for LocalIrqCellGuard<'a, T> {
+    fn drop(&mut self) {
+        self.cell.borrowed.set(false);
+    }
+}
+
+impl<'a, T: ?Sized> Deref for LocalIrqCellGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for LocalIrqCellGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.cell.data.get() }
+    }
+}
@@ -0,0 +1,25 @@
+//! A global switch that makes the "or break" lock functions break locks immediately,
+//! e.g., [`MutexIrqSafe::lock_or_break()`](crate::MutexIrqSafe::lock_or_break).
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static SYSTEM_PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Marks the system as panicking, such that [`MutexIrqSafe::lock_or_break()`],
+/// [`RwLockIrqSafe::write_or_break()`] and [`HoldingMutex::lock_or_break()`]
+/// break a held lock immediately instead of waiting for it.
+///
+/// This is intended to be invoked at the start of a panic handler or crash-dump path,
+/// and cannot be undone.
+///
+/// [`MutexIrqSafe::lock_or_break()`]: crate::MutexIrqSafe::lock_or_break
+/// [`RwLockIrqSafe::write_or_break()`]: crate::RwLockIrqSafe::write_or_break
+/// [`HoldingMutex::lock_or_break()`]: crate::HoldingMutex::lock_or_break
+pub fn set_system_panicking() {
+    SYSTEM_PANICKING.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether [`set_system_panicking()`] has been invoked.
+pub fn is_system_panicking() -> bool {
+    SYSTEM_PANICKING.load(Ordering::SeqCst)
+}
@@ -0,0 +1,237 @@
+//! A backend for model-checking the lock types with `loom`, enabled via `--cfg loom`.
+//!
+//! This swaps the [`spin`] locks used internally by [`MutexIrqSafe`](crate::MutexIrqSafe)
+//! and [`RwLockIrqSafe`](crate::RwLockIrqSafe) for equivalent locks built on
+//! loom-instrumented atomics, using the same acquire/release protocol as [`spin`].
+//! It also simulates a per-thread interrupt-enabled flag,
+//! treating each loom thread as its own CPU.
+
+use core::{
+    cell::Cell,
+    ops::{Deref, DerefMut},
+};
+use loom::{
+    cell::{MutPtr, ConstPtr, UnsafeCell},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+loom::thread_local! {
+    static ENABLED: Cell<bool> = Cell::new(true);
+}
+
+/// Enables simulated interrupts on the current loom thread.
+#[inline(always)]
+pub fn enable_interrupts() {
+    ENABLED.with(|e| e.set(true));
+}
+
+/// Disables simulated interrupts on the current loom thread.
+#[inline(always)]
+pub fn disable_interrupts() {
+    ENABLED.with(|e| e.set(false));
+}
+
+/// Enables simulated interrupts on the current loom thread;
+/// there are no interrupts to wait for under loom.
+#[inline(always)]
+pub fn enable_interrupts_and_halt() {
+    enable_interrupts();
+}
+
+/// Does nothing, as there are no interrupts to wait for under loom.
+#[inline(always)]
+pub fn enable_interrupts_and_wait() { }
+
+/// Returns whether simulated interrupts are enabled on the current loom thread.
+#[inline(always)]
+pub fn interrupts_enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// A loom-instrumented equivalent of [`spin::Mutex`].
+pub(crate) struct Mutex<T: ?Sized> {
+    lock: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+pub(crate) struct MutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a AtomicBool,
+    // Must stop tracking the access *before* the lock is released.
+    data: Option<MutPtr<T>>,
+}
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(data: T) -> Mutex<T> {
+        Mutex {
+            lock: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub(crate) fn is_locked(&self) -> bool {
+        self.lock.load(Ordering::Relaxed)
+    }
+
+    pub(crate) unsafe fn force_unlock(&self) {
+        self.lock.store(false, Ordering::Release);
+    }
+
+    pub(crate) fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard {
+                lock: &self.lock,
+                data: Some(self.data.get_mut()),
+            })
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut().with(|data| unsafe { &mut *data })
+    }
+
+    /// Accesses through the returned pointer are not tracked by loom.
+    pub(crate) fn as_mut_ptr(&self) -> *mut T {
+        self.data.get_mut().with(|data| data)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.data = None;
+        self.lock.store(false, Ordering::Release);
+    }
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref().unwrap().deref() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.data.as_ref().unwrap().deref() }
+    }
+}
+
+const READER: usize = 1 << 2;
+const WRITER: usize = 1;
+
+/// A loom-instrumented equivalent of [`spin::RwLock`].
+pub(crate) struct RwLock<T: ?Sized> {
+    lock: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+pub(crate) struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a AtomicUsize,
+    data: Option<ConstPtr<T>>,
+}
+
+pub(crate) struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a AtomicUsize,
+    data: Option<MutPtr<T>>,
+}
+
+impl<T> RwLock<T> {
+    pub(crate) fn new(data: T) -> RwLock<T> {
+        RwLock {
+            lock: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub(crate) fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let value = self.lock.fetch_add(READER, Ordering::Acquire);
+        if value & WRITER != 0 {
+            self.lock.fetch_sub(READER, Ordering::Release);
+            None
+        } else {
+            Some(RwLockReadGuard {
+                lock: &self.lock,
+                data: Some(self.data.get()),
+            })
+        }
+    }
+
+    pub(crate) fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.lock
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard {
+                lock: &self.lock,
+                data: Some(self.data.get_mut()),
+            })
+    }
+
+    pub(crate) fn reader_count(&self) -> usize {
+        self.lock.load(Ordering::Relaxed) / READER
+    }
+
+    pub(crate) fn writer_count(&self) -> usize {
+        self.lock.load(Ordering::Relaxed) & WRITER
+    }
+
+    pub(crate) unsafe fn force_read_decrement(&self) {
+        self.lock.fetch_sub(READER, Ordering::Release);
+    }
+
+    pub(crate) unsafe fn force_write_unlock(&self) {
+        self.lock.fetch_and(!WRITER, Ordering::Release);
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut().with(|data| unsafe { &mut *data })
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.data = None;
+        self.lock.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.data = None;
+        self.lock.fetch_and(!WRITER, Ordering::Release);
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref().unwrap().deref() }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref().unwrap().deref() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.data.as_ref().unwrap().deref() }
+    }
+}
@@ -0,0 +1,26 @@
+use crate::held_softirqs::HeldSoftirqs;
+use crate::holding_mutex::{HoldingMutex, HoldingMutexGuard};
+
+/// A spinlock that holds off deferred interrupt handlers (softirqs), based on [spin::Mutex].
+///
+/// This is like [`MutexIrqSafe`](crate::MutexIrqSafe), except that it only holds
+/// softirqs (see [`hold_softirqs()`](crate::hold_softirqs)) for the duration of the lock being held,
+/// leaving hardware interrupts enabled.
+/// Thus, it must only be used for data that is shared between regular threads
+/// and deferred handlers, never with hardware interrupt handlers.
+///
+/// # Example
+///
+/// ```no_run
+/// let spin_mutex = irq_safety::MutexBhSafe::new(0);
+/// {
+///     let mut data = spin_mutex.lock();
+///     // Softirqs are now held on this CPU, but hardware interrupts are not.
+///     *data = 2;
+/// }
+/// assert_eq!(*spin_mutex.lock(), 2);
+/// ```
+pub type MutexBhSafe<T> = HoldingMutex<T, HeldSoftirqs>;
+
+/// A guard to which the data protected by a [`MutexBhSafe`] can be accessed.
+pub type MutexBhSafeGuard<'a, T> = HoldingMutexGuard<'a, T, HeldSoftirqs>;
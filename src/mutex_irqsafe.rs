@@ -1,5 +1,8 @@
-use core::{fmt, ops::{Deref, DerefMut}};
-use spin::{Mutex, MutexGuard};
+use core::{fmt, ops::{Deref, DerefMut}, ptr};
+#[cfg(not(loom))]
+use spin::mutex::{SpinMutex as Mutex, SpinMutexGuard as MutexGuard};
+#[cfg(loom)]
+use crate::loom_backend::{Mutex, MutexGuard};
 use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
 
 /// This type provides interrupt-safe MUTual EXclusion based on [spin::Mutex].
@@ -11,7 +14,8 @@ use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
 /// - It may be used outside the runtime.
 ///   - A normal Mutex will fail when used without the runtime, this will just lock
 ///   - When the runtime is present, it will call the deschedule function when appropriate
-/// - No lock poisoning. When a fail occurs when the lock is held, no guarantees are made
+/// - No lock poisoning. When a fail occurs when the lock is held, no guarantees are made;
+///   see [`PoisoningMutexIrqSafe`](crate::PoisoningMutexIrqSafe) for a poisoning variant.
 ///
 /// When calling rust functions from bare threads, such as C `pthread`s, this lock will be very
 /// helpful. In other cases however, you are encouraged to use the locks from the standard
@@ -72,6 +76,10 @@ use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
 /// assert_eq!(answer, numthreads);
 /// ```
 pub struct MutexIrqSafe<T: ?Sized> {
+    #[cfg(feature = "deadlock_detection")]
+    owner: crate::deadlock_detection::Owner,
+    #[cfg(feature = "lock_registry")]
+    registration: crate::lock_registry::Registration,
     lock: Mutex<T>,
 }
 
@@ -79,7 +87,16 @@ pub struct MutexIrqSafe<T: ?Sized> {
 ///
 /// When the guard falls out of scope it will release the lock.
 pub struct MutexIrqSafeGuard<'a, T: ?Sized + 'a> {
+    // The lock this guard was obtained from, used to re-acquire it after waiting.
+    pub(crate) mutex: &'a MutexIrqSafe<T>,
+    // Whether the lock was forcibly taken from its previous holder.
+    broken: bool,
+    // `_owner` must be dropped before `guard` releases the lock.
+    #[cfg(feature = "deadlock_detection")]
+    _owner: crate::deadlock_detection::OwnerRecord<'a>,
     guard: MutexGuard<'a, T>,
+    #[cfg(feature = "sim")]
+    _live: crate::sim::LiveGuard,
     // `_held_irq` will be dropped after `guard`.
     // Rust guarantees that fields are dropped in the order of declaration.
     _held_irq: HeldInterrupts,
@@ -105,8 +122,27 @@ impl<T> MutexIrqSafe<T> {
     ///     drop(lock);
     /// }
     /// ```
+    #[cfg(not(loom))]
     pub const fn new(data: T) -> MutexIrqSafe<T> {
         MutexIrqSafe {
+            #[cfg(feature = "deadlock_detection")]
+            owner: crate::deadlock_detection::Owner::new(),
+            #[cfg(feature = "lock_registry")]
+            registration: crate::lock_registry::Registration::new(),
+            lock: Mutex::new(data),
+        }
+    }
+
+    /// Creates a new spinlock wrapping the supplied data.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[cfg(loom)]
+    pub fn new(data: T) -> MutexIrqSafe<T> {
+        MutexIrqSafe {
+            #[cfg(feature = "deadlock_detection")]
+            owner: crate::deadlock_detection::Owner::new(),
+            #[cfg(feature = "lock_registry")]
+            registration: crate::lock_registry::Registration::new(),
             lock: Mutex::new(data),
         }
     }
@@ -116,6 +152,12 @@ impl<T> MutexIrqSafe<T> {
     pub fn into_inner(self) -> T {
         self.lock.into_inner()
     }
+
+    /// Returns a raw pointer to the underlying data, without locking.
+    #[inline(always)]
+    pub(crate) fn as_mut_ptr(&self) -> *mut T {
+        self.lock.as_mut_ptr()
+    }
 }
 
 impl<T: ?Sized> MutexIrqSafe<T> {
@@ -135,15 +177,59 @@ impl<T: ?Sized> MutexIrqSafe<T> {
     ///
     /// ```
     #[inline(always)]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
     pub fn lock(&self) -> MutexIrqSafeGuard<T> {
         loop {
             match self.try_lock() {
                 Some(guard) => return guard,
                 _ => {}
             }
+            #[cfg(loom)]
+            loom::hint::spin_loop();
+            #[cfg(feature = "deadlock_detection")]
+            self.owner.check(
+                self as *const _ as *const () as usize,
+                "MutexIrqSafe",
+                core::panic::Location::caller(),
+            );
+            #[cfg(feature = "sim")]
+            crate::sim::on_spin(
+                self as *const _ as *const () as usize,
+                "MutexIrqSafe",
+                crate::sim::Access::Exclusive,
+                core::panic::Location::caller(),
+            );
         }
     }
 
+    /// Locks the spinlock and invokes `f` with the protected data, returning its result.
+    ///
+    /// The lock is released and interrupts are restored as soon as `f` returns,
+    /// so the guard cannot accidentally outlive the intended critical section.
+    ///
+    /// ```no_run
+    /// let mylock = irq_safety::MutexIrqSafe::new(0);
+    /// let new_value = mylock.with(|data| {
+    ///     *data += 1;
+    ///     *data
+    /// });
+    /// assert_eq!(new_value, 1);
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut *self.lock())
+    }
+
+    /// Attempts to lock the spinlock and, if successful, invokes `f` with the protected data.
+    ///
+    /// Returns `None` without invoking `f` if the lock is already held.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        self.try_lock().map(|mut guard| f(&mut *guard))
+    }
+
     /// Returns `true` if the lock is currently held.
     ///
     /// # Safety
@@ -169,15 +255,73 @@ impl<T: ?Sized> MutexIrqSafe<T> {
     /// Tries to lock the MutexIrqSafe. If it is already locked, it will return None. Otherwise it returns
     /// a guard within Some.
     #[inline(always)]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
     pub fn try_lock(&self) -> Option<MutexIrqSafeGuard<T>> {
         if self.lock.is_locked() { return None; }
         let _held_irq = hold_interrupts();
+        // `Location::caller()` must be invoked outside of the closure below.
+        #[cfg(any(feature = "sim", feature = "deadlock_detection"))]
+        let location = core::panic::Location::caller();
         self.lock.try_lock().map(|guard| MutexIrqSafeGuard {
+            mutex: self,
+            broken: false,
+            #[cfg(feature = "deadlock_detection")]
+            _owner: self.owner.acquired(location),
             guard,
+            #[cfg(feature = "sim")]
+            _live: crate::sim::LiveGuard::new(
+                self as *const _ as *const () as usize,
+                "MutexIrqSafe",
+                crate::sim::Access::Exclusive,
+                location,
+            ),
             _held_irq,
         })
     }
 
+    /// Locks the spinlock, forcibly taking it from its current holder
+    /// if it cannot be acquired within `spins` attempts.
+    ///
+    /// This is intended for panic handlers and crash-dump paths, where the lock
+    /// may be held by a CPU that will never release it, e.g., the current CPU.
+    /// Whether the lock was taken forcibly can be checked with [`MutexIrqSafeGuard::is_broken()`].
+    ///
+    /// The lock is broken after a single attempt if [`set_system_panicking()`](crate::set_system_panicking)
+    /// has been invoked or, with the `deadlock_detection` feature enabled,
+    /// if the current CPU is known to hold the lock.
+    ///
+    /// If the previous holder is still running, it may access the data concurrently,
+    /// and dropping its guard will release the lock out from under the new holder.
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn lock_or_break(&self, spins: usize) -> MutexIrqSafeGuard<'_, T> {
+        let spins = if crate::lock_break::is_system_panicking() { 0 } else { spins };
+        #[cfg(feature = "deadlock_detection")]
+        let spins = if self.held_by_current_cpu() { 0 } else { spins };
+
+        // Even without waiting, a free lock is taken normally rather than broken.
+        for _ in 0..spins.max(1) {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+        loop {
+            // SAFETY: the caller accepts that the previous holder is presumed to never release the lock.
+            unsafe { self.lock.force_unlock() };
+            if let Some(mut guard) = self.try_lock() {
+                guard.broken = true;
+                return guard;
+            }
+        }
+    }
+
+    /// Returns whether the current CPU holds the lock, if that can be determined.
+    #[cfg(feature = "deadlock_detection")]
+    #[inline(always)]
+    pub(crate) fn held_by_current_cpu(&self) -> bool {
+        self.owner.held_by_current_cpu()
+    }
+
     /// Returns a mutable reference to the underlying data.
     ///
     /// Since this call borrows the [`MutexIrqSafe`] mutably, and a mutable reference is guaranteed to be exclusive in Rust,
@@ -197,6 +341,31 @@ impl<T: ?Sized> MutexIrqSafe<T> {
     }
 }
 
+#[cfg(feature = "lock_registry")]
+impl<T: Send + 'static> MutexIrqSafe<T> {
+    /// Adds this lock to the global registry under the given `name`,
+    /// such that it is listed by [`dump_held_locks()`](crate::dump_held_locks) while held.
+    ///
+    /// Registering a lock again has no effect; it keeps its original name.
+    pub fn register(&'static self, name: &'static str) {
+        self.registration.register(name, self);
+    }
+}
+
+#[cfg(feature = "lock_registry")]
+impl<T: Send> crate::lock_registry::RegisteredLock for MutexIrqSafe<T> {
+    fn is_held(&self) -> bool {
+        self.is_locked()
+    }
+
+    fn describe(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        write!(w, "MutexIrqSafe locked")?;
+        #[cfg(feature = "deadlock_detection")]
+        self.owner.describe(w)?;
+        Ok(())
+    }
+}
+
 impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexIrqSafe<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.lock.try_lock() {
@@ -212,6 +381,67 @@ impl<T: ?Sized + Default> Default for MutexIrqSafe<T> {
     }
 }
 
+impl<'a, T: ?Sized> MutexIrqSafeGuard<'a, T> {
+    /// Returns `true` if this guard was obtained by [`MutexIrqSafe::lock_or_break()`]
+    /// forcibly taking the lock from its previous holder.
+    ///
+    /// This is an associated function rather than a method,
+    /// to avoid conflicting with methods on the protected data.
+    pub fn is_broken(s: &Self) -> bool {
+        s.broken
+    }
+
+    /// Temporarily unlocks the mutex and restores interrupts to their prior state
+    /// while invoking `f`, then re-acquires both before returning.
+    ///
+    /// This is an associated function rather than a method,
+    /// to avoid conflicting with methods on the protected data.
+    ///
+    /// If `f` panics, the guard cannot be restored, as re-acquiring the lock while unwinding
+    /// could deadlock or panic again, so this panics again instead, which aborts.
+    /// Panics are detected via the hook registered with [`set_panicking_hook()`](crate::set_panicking_hook);
+    /// without it, the lock is re-acquired while unwinding.
+    ///
+    /// ```no_run
+    /// use irq_safety::{MutexIrqSafe, MutexIrqSafeGuard};
+    ///
+    /// let mylock = MutexIrqSafe::new(0);
+    /// let mut guard = mylock.lock();
+    /// *guard += 1;
+    /// MutexIrqSafeGuard::unlocked(&mut guard, || {
+    ///     // Other CPUs and interrupt handlers can acquire the lock here.
+    /// });
+    /// *guard += 1;
+    /// ```
+    pub fn unlocked<R, F: FnOnce() -> R>(s: &mut Self, f: F) -> R {
+        /// Re-acquires the lock into `slot` when dropped, or aborts if unwinding.
+        struct Relock<'g, 'a, T: ?Sized> {
+            slot: &'g mut MutexIrqSafeGuard<'a, T>,
+            mutex: &'a MutexIrqSafe<T>,
+            broken: bool,
+        }
+        impl<'g, 'a, T: ?Sized> Drop for Relock<'g, 'a, T> {
+            fn drop(&mut self) {
+                if crate::poison::panicking() {
+                    // The empty `slot` would otherwise be dropped while unwinding.
+                    panic!("MutexIrqSafeGuard::unlocked(): cannot re-acquire the lock while panicking");
+                }
+                let mut guard = self.mutex.lock();
+                guard.broken = self.broken;
+                unsafe { ptr::write(self.slot, guard); }
+            }
+        }
+
+        let mutex = s.mutex;
+        let broken = s.broken;
+        // SAFETY: the guard in `s` is dropped here and always rewritten by `Relock::drop()`
+        // before it can be accessed again, unless that aborts.
+        unsafe { ptr::drop_in_place(s); }
+        let _relock = Relock { slot: s, mutex, broken };
+        f()
+    }
+}
+
 impl<'a, T: ?Sized> Deref for MutexIrqSafeGuard<'a, T> {
     type Target = T;
 
@@ -0,0 +1,26 @@
+use crate::held_preemption::HeldPreemption;
+use crate::holding_mutex::{HoldingMutex, HoldingMutexGuard};
+
+/// A spinlock that disables preemption, based on [spin::Mutex].
+///
+/// This is like [`MutexIrqSafe`](crate::MutexIrqSafe), except that it only holds
+/// preemption (see [`hold_preemption()`](crate::hold_preemption)) for the duration of the lock being held,
+/// leaving interrupts enabled.
+/// Thus, it must only be used for data that is shared between tasks,
+/// never with interrupt handlers or deferred handlers.
+///
+/// # Example
+///
+/// ```no_run
+/// let spin_mutex = irq_safety::MutexPreemptSafe::new(0);
+/// {
+///     let mut data = spin_mutex.lock();
+///     // Preemption is now disabled, but interrupts are not.
+///     *data = 2;
+/// }
+/// assert_eq!(*spin_mutex.lock(), 2);
+/// ```
+pub type MutexPreemptSafe<T> = HoldingMutex<T, HeldPreemption>;
+
+/// A guard to which the data protected by a [`MutexPreemptSafe`] can be accessed.
+pub type MutexPreemptSafeGuard<'a, T> = HoldingMutexGuard<'a, T, HeldPreemption>;
@@ -0,0 +1,155 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use crate::held_interrupts::hold_interrupts;
+
+/// A hook that lets this crate determine which CPU it is currently running on.
+///
+/// This must be implemented by the OS, e.g., by reading the local APIC ID
+/// on x86_64 or the `MPIDR_EL1` register on aarch64.
+///
+/// # Safety
+///
+/// Types such as [`PerCpu`] and [`ReentrantMutexIrqSafe`](crate::ReentrantMutexIrqSafe)
+/// rely on this for soundness, so implementations must guarantee that:
+/// * no two CPUs ever return the same index at the same time, and
+/// * the current task cannot migrate to another CPU while interrupts are held,
+///   such that the returned index remains valid until they are restored.
+pub unsafe trait CpuId {
+    /// Returns the index of the current CPU.
+    ///
+    /// This is only invoked while interrupts are held, so the current task
+    /// cannot migrate to another CPU while the returned value is in use.
+    fn current_cpu() -> usize;
+}
+
+/// A container that holds one instance of `T` for each of up to `N` CPUs.
+///
+/// The current CPU's instance is accessed via [`PerCpu::with()`],
+/// which holds interrupts for the duration of the access,
+/// such that an interrupt handler on the same CPU cannot observe it mid-modification.
+/// No spinning is ever needed, because each instance is only accessed by its own CPU.
+///
+/// The current CPU is determined by the user-supplied [`CpuId`] implementation `C`.
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::{CpuId, PerCpu};
+///
+/// struct MyCpuId;
+/// unsafe impl CpuId for MyCpuId {
+///     fn current_cpu() -> usize { 0 /* read from hardware */ }
+/// }
+///
+/// static IRQ_COUNTS: PerCpu<usize, 4, MyCpuId> = PerCpu::new([0; 4]);
+///
+/// fn on_interrupt() {
+///     IRQ_COUNTS.with(|count| *count += 1);
+/// }
+///
+/// fn total_interrupts() -> usize {
+///     // SAFETY: this is only a statistic, and no CPU holds a reference
+///     // to its counter across this call.
+///     unsafe { IRQ_COUNTS.iter().sum() }
+/// }
+/// ```
+pub struct PerCpu<T, const N: usize, C: CpuId> {
+    data: UnsafeCell<[T; N]>,
+    /// Whether each CPU's instance is currently being accessed via `with()`.
+    borrowed: [AtomicBool; N],
+    _cpu_id: PhantomData<fn() -> C>,
+}
+
+/// Clears a CPU's `borrowed` flag when dropped.
+struct Borrowed<'a>(&'a AtomicBool);
+
+impl Drop for Borrowed<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+unsafe impl<T: Send, const N: usize, C: CpuId> Sync for PerCpu<T, N, C> {}
+unsafe impl<T: Send, const N: usize, C: CpuId> Send for PerCpu<T, N, C> {}
+
+impl<T, const N: usize, C: CpuId> PerCpu<T, N, C> {
+    /// Creates a new `PerCpu` container from one instance of `T` per CPU.
+    pub const fn new(data: [T; N]) -> PerCpu<T, N, C> {
+        PerCpu {
+            data: UnsafeCell::new(data),
+            borrowed: [const { AtomicBool::new(false) }; N],
+            _cpu_id: PhantomData,
+        }
+    }
+
+    /// Consumes this `PerCpu` container, returning all CPUs' instances.
+    #[inline(always)]
+    pub fn into_inner(self) -> [T; N] {
+        self.data.into_inner()
+    }
+
+    /// Invokes `f` with the current CPU's instance,
+    /// holding interrupts for the duration of the call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current CPU's index is not less than `N`,
+    /// or if the current CPU's instance is already being accessed,
+    /// e.g., from a nested call to `with()`.
+    #[track_caller]
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let _held_irq = hold_interrupts();
+        let cpu = C::current_cpu();
+        assert!(cpu < N, "PerCpu: current CPU {} is out of bounds (max {})", cpu, N);
+        if self.borrowed[cpu].swap(true, Ordering::Acquire) {
+            panic!("PerCpu at {:p}: instance for CPU {} is already borrowed", self, cpu);
+        }
+        // Released even if `f` panics, before interrupts are restored.
+        let _borrowed = Borrowed(&self.borrowed[cpu]);
+        f(unsafe { &mut *self.slot(cpu) })
+    }
+
+    /// Returns an iterator over all CPUs' instances, e.g., to aggregate statistics.
+    ///
+    /// # Safety
+    ///
+    /// For the lifetime of the returned iterator and the references it yields,
+    /// no CPU may be mutating its instance via [`PerCpu::with()`],
+    /// unless all mutable state within `T` is itself synchronized (e.g., atomics).
+    /// The caller is responsible for upholding this, e.g., by only reading
+    /// data that is written with atomic operations, or by stopping all other CPUs.
+    pub unsafe fn iter(&self) -> impl Iterator<Item = &T> + '_ where T: Sync {
+        (0..N).map(move |cpu| unsafe { &*self.slot(cpu) })
+    }
+
+    /// Returns an iterator that yields mutable references to all CPUs' instances.
+    ///
+    /// Since this call borrows the [`PerCpu`] mutably, no synchronization is needed.
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.data.get_mut().iter_mut()
+    }
+
+    /// Returns a mutable reference to the instance for the given `cpu`, if it exists.
+    ///
+    /// Since this call borrows the [`PerCpu`] mutably, no synchronization is needed.
+    #[inline]
+    pub fn get_mut(&mut self, cpu: usize) -> Option<&mut T> {
+        self.data.get_mut().get_mut(cpu)
+    }
+
+    #[inline(always)]
+    fn slot(&self, cpu: usize) -> *mut T {
+        unsafe { (self.data.get() as *mut T).add(cpu) }
+    }
+}
+
+impl<T, const N: usize, C: CpuId> fmt::Debug for PerCpu<T, N, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PerCpu {{ cpus: {} }}", N)
+    }
+}
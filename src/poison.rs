@@ -0,0 +1,158 @@
+//! Error types and panic detection for the poisoning lock types,
+//! mirroring [`std::sync::PoisonError`](https://doc.rust-lang.org/std/sync/struct.PoisonError.html).
+//!
+//! A poisoning lock is marked as poisoned if a write guard is dropped while its task is panicking,
+//! i.e., while unwinding. Since `no_std` code cannot determine whether it is panicking,
+//! the OS must provide that via [`set_panicking_hook()`]; until then, locks are never poisoned.
+
+use core::{
+    fmt,
+    mem,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// The registered panicking hook, as a `fn() -> bool`, or `0` if none has been registered.
+static PANICKING_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers the `hook` that determines whether the current task is panicking,
+/// e.g., `std::thread::panicking` in hosted environments.
+///
+/// This replaces any previously-registered hook.
+pub fn set_panicking_hook(hook: fn() -> bool) {
+    PANICKING_HOOK.store(hook as usize, Ordering::Release);
+}
+
+/// Returns whether the current task is panicking, according to the registered hook.
+pub(crate) fn panicking() -> bool {
+    match PANICKING_HOOK.load(Ordering::Acquire) {
+        0 => false,
+        hook => {
+            // SAFETY: this was stored from a function pointer of this exact type.
+            let hook = unsafe { mem::transmute::<usize, fn() -> bool>(hook) };
+            hook()
+        }
+    }
+}
+
+/// The poisoned state of a lock.
+pub(crate) struct PoisonFlag(AtomicBool);
+
+/// Whether the task was already panicking when it acquired a lock.
+pub(crate) struct PoisonGuard {
+    panicking: bool,
+}
+
+impl PoisonFlag {
+    pub(crate) const fn new() -> PoisonFlag {
+        PoisonFlag(AtomicBool::new(false))
+    }
+
+    /// Invoked after acquiring a lock for writing.
+    pub(crate) fn guard(&self) -> PoisonGuard {
+        PoisonGuard { panicking: panicking() }
+    }
+
+    /// Invoked before releasing a lock that was acquired for writing.
+    /// Poisons the lock if the task started panicking while it held the lock.
+    pub(crate) fn done(&self, guard: &PoisonGuard) {
+        if !guard.panicking && panicking() {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns `Ok(value)`, or `Err` containing `value` if the lock is poisoned.
+    pub(crate) fn result<T>(&self, value: T) -> LockResult<T> {
+        if self.get() { Err(PoisonError::new(value)) } else { Ok(value) }
+    }
+}
+
+/// An error returned when acquiring a lock that is poisoned.
+///
+/// The lock was still acquired, and its guard can be accessed via [`PoisonError::into_inner()`].
+pub struct PoisonError<T> {
+    guard: T,
+}
+
+impl<T> PoisonError<T> {
+    /// Creates a `PoisonError` containing the given guard.
+    pub fn new(guard: T) -> PoisonError<T> {
+        PoisonError { guard }
+    }
+
+    /// Consumes this error, returning the guard, which still holds the lock.
+    pub fn into_inner(self) -> T {
+        self.guard
+    }
+
+    /// Returns a reference to the guard.
+    pub fn get_ref(&self) -> &T {
+        &self.guard
+    }
+
+    /// Returns a mutable reference to the guard.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> fmt::Debug for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PoisonError {{ .. }}")
+    }
+}
+
+impl<T> fmt::Display for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "poisoned lock: another task failed inside")
+    }
+}
+
+impl<T> core::error::Error for PoisonError<T> {}
+
+/// An error returned when attempting to acquire a lock without blocking.
+pub enum TryLockError<T> {
+    /// The lock was acquired, but it is poisoned.
+    Poisoned(PoisonError<T>),
+    /// The lock could not be acquired without blocking.
+    WouldBlock,
+}
+
+impl<T> From<PoisonError<T>> for TryLockError<T> {
+    fn from(err: PoisonError<T>) -> TryLockError<T> {
+        TryLockError::Poisoned(err)
+    }
+}
+
+impl<T> fmt::Debug for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryLockError::Poisoned(err) => write!(f, "Poisoned({:?})", err),
+            TryLockError::WouldBlock => write!(f, "WouldBlock"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryLockError::Poisoned(err) => fmt::Display::fmt(err, f),
+            TryLockError::WouldBlock => write!(f, "try_lock failed because the operation would block"),
+        }
+    }
+}
+
+impl<T> core::error::Error for TryLockError<T> {}
+
+/// The result of acquiring a poisoning lock.
+pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+/// The result of attempting to acquire a poisoning lock without blocking.
+pub type TryLockResult<G> = Result<G, TryLockError<G>>;
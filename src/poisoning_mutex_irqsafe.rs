@@ -0,0 +1,160 @@
+use core::{fmt, ops::{Deref, DerefMut}};
+use crate::mutex_irqsafe::{MutexIrqSafe, MutexIrqSafeGuard};
+use crate::poison::{LockResult, PoisonFlag, PoisonGuard, TryLockError, TryLockResult};
+
+/// A [`MutexIrqSafe`] with lock poisoning, mirroring [`std::sync::Mutex`](https://doc.rust-lang.org/std/sync/struct.Mutex.html).
+///
+/// If a task panics while holding this lock, the lock becomes poisoned,
+/// and all subsequent attempts to lock it return an `Err` containing the guard,
+/// until [`clear_poison()`](Self::clear_poison) is invoked.
+/// Panics are detected via the hook registered with [`set_panicking_hook()`](crate::set_panicking_hook).
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::PoisoningMutexIrqSafe;
+///
+/// let lock = PoisoningMutexIrqSafe::new(0);
+/// *lock.lock().unwrap() += 1;
+///
+/// // Recover the data from a poisoned lock.
+/// let mut data = lock.lock().unwrap_or_else(|err| err.into_inner());
+/// *data += 1;
+/// ```
+pub struct PoisoningMutexIrqSafe<T: ?Sized> {
+    poison: PoisonFlag,
+    lock: MutexIrqSafe<T>,
+}
+
+/// A guard to which the protected data can be accessed.
+///
+/// When the guard falls out of scope it will release the lock,
+/// poisoning it first if the task started panicking while the lock was held.
+pub struct PoisoningMutexIrqSafeGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a PoisoningMutexIrqSafe<T>,
+    poison: PoisonGuard,
+    guard: MutexIrqSafeGuard<'a, T>,
+}
+
+impl<T> PoisoningMutexIrqSafe<T> {
+    /// Creates a new, unpoisoned lock wrapping the supplied data.
+    #[cfg(not(loom))]
+    pub const fn new(data: T) -> PoisoningMutexIrqSafe<T> {
+        PoisoningMutexIrqSafe {
+            poison: PoisonFlag::new(),
+            lock: MutexIrqSafe::new(data),
+        }
+    }
+
+    /// Creates a new, unpoisoned lock wrapping the supplied data.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[cfg(loom)]
+    pub fn new(data: T) -> PoisoningMutexIrqSafe<T> {
+        PoisoningMutexIrqSafe {
+            poison: PoisonFlag::new(),
+            lock: MutexIrqSafe::new(data),
+        }
+    }
+
+    /// Consumes this lock, returning the underlying data,
+    /// or an `Err` containing it if the lock is poisoned.
+    pub fn into_inner(self) -> LockResult<T> {
+        let PoisoningMutexIrqSafe { poison, lock } = self;
+        poison.result(lock.into_inner())
+    }
+}
+
+impl<T: ?Sized> PoisoningMutexIrqSafe<T> {
+    /// Locks the mutex, holding interrupts, and returns a guard.
+    ///
+    /// Returns an `Err` containing the guard if the lock is poisoned.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn lock(&self) -> LockResult<PoisoningMutexIrqSafeGuard<'_, T>> {
+        let guard = self.lock.lock();
+        self.poison.result(PoisoningMutexIrqSafeGuard {
+            mutex: self,
+            poison: self.poison.guard(),
+            guard,
+        })
+    }
+
+    /// Tries to lock the mutex without spinning.
+    ///
+    /// Returns [`TryLockError::WouldBlock`] if it is already locked,
+    /// or [`TryLockError::Poisoned`] containing the guard if the lock is poisoned.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_lock(&self) -> TryLockResult<PoisoningMutexIrqSafeGuard<'_, T>> {
+        let guard = self.lock.try_lock().ok_or(TryLockError::WouldBlock)?;
+        Ok(self.poison.result(PoisoningMutexIrqSafeGuard {
+            mutex: self,
+            poison: self.poison.guard(),
+            guard,
+        })?)
+    }
+
+    /// Returns `true` if the lock is poisoned.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clears the poisoned state of the lock,
+    /// e.g., after the protected data has been restored to a consistent state.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// Returns a mutable reference to the underlying data,
+    /// or an `Err` containing it if the lock is poisoned.
+    ///
+    /// Since this call borrows the lock mutably, no actual locking needs to take place.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.poison.result(self.lock.get_mut())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PoisoningMutexIrqSafe<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.lock.try_lock() {
+            Some(guard) => write!(f, "PoisoningMutexIrqSafe {{ data: {:?}, poisoned: {} }}", &*guard, self.poison.get()),
+            None => write!(f, "PoisoningMutexIrqSafe {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default> Default for PoisoningMutexIrqSafe<T> {
+    fn default() -> PoisoningMutexIrqSafe<T> {
+        PoisoningMutexIrqSafe::new(Default::default())
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PoisoningMutexIrqSafeGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for PoisoningMutexIrqSafeGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for PoisoningMutexIrqSafeGuard<'a, T> {
+    fn drop(&mut self) {
+        // This runs before `self.guard` releases the lock.
+        self.mutex.poison.done(&self.poison);
+    }
+}
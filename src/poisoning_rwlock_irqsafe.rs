@@ -0,0 +1,185 @@
+use core::{fmt, ops::{Deref, DerefMut}};
+use crate::rwlock_irqsafe::{RwLockIrqSafe, RwLockIrqSafeReadGuard, RwLockIrqSafeWriteGuard};
+use crate::poison::{LockResult, PoisonFlag, PoisonGuard, TryLockError, TryLockResult};
+
+/// A [`RwLockIrqSafe`] with lock poisoning, mirroring [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// If a task panics while holding this lock for writing, the lock becomes poisoned,
+/// and all subsequent attempts to lock it return an `Err` containing the guard,
+/// until [`clear_poison()`](Self::clear_poison) is invoked.
+/// Panics while holding the lock for reading do not poison it.
+/// Panics are detected via the hook registered with [`set_panicking_hook()`](crate::set_panicking_hook).
+pub struct PoisoningRwLockIrqSafe<T: ?Sized> {
+    poison: PoisonFlag,
+    rwlock: RwLockIrqSafe<T>,
+}
+
+/// A guard to which the protected data can be read.
+///
+/// When the guard falls out of scope it will decrement the read count,
+/// potentially releasing the lock.
+pub struct PoisoningRwLockIrqSafeReadGuard<'a, T: 'a + ?Sized> {
+    guard: RwLockIrqSafeReadGuard<'a, T>,
+}
+
+/// A guard to which the protected data can be written.
+///
+/// When the guard falls out of scope it will release the lock,
+/// poisoning it first if the task started panicking while the lock was held.
+pub struct PoisoningRwLockIrqSafeWriteGuard<'a, T: 'a + ?Sized> {
+    rwlock: &'a PoisoningRwLockIrqSafe<T>,
+    poison: PoisonGuard,
+    guard: RwLockIrqSafeWriteGuard<'a, T>,
+}
+
+impl<T> PoisoningRwLockIrqSafe<T> {
+    /// Creates a new, unpoisoned lock wrapping the supplied data.
+    #[cfg(not(loom))]
+    pub const fn new(data: T) -> PoisoningRwLockIrqSafe<T> {
+        PoisoningRwLockIrqSafe {
+            poison: PoisonFlag::new(),
+            rwlock: RwLockIrqSafe::new(data),
+        }
+    }
+
+    /// Creates a new, unpoisoned lock wrapping the supplied data.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[cfg(loom)]
+    pub fn new(data: T) -> PoisoningRwLockIrqSafe<T> {
+        PoisoningRwLockIrqSafe {
+            poison: PoisonFlag::new(),
+            rwlock: RwLockIrqSafe::new(data),
+        }
+    }
+
+    /// Consumes this lock, returning the underlying data,
+    /// or an `Err` containing it if the lock is poisoned.
+    pub fn into_inner(self) -> LockResult<T> {
+        let PoisoningRwLockIrqSafe { poison, rwlock } = self;
+        poison.result(rwlock.into_inner())
+    }
+}
+
+impl<T: ?Sized> PoisoningRwLockIrqSafe<T> {
+    /// Locks this rwlock with shared read access, holding interrupts, and returns a guard.
+    ///
+    /// Returns an `Err` containing the guard if the lock is poisoned.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn read(&self) -> LockResult<PoisoningRwLockIrqSafeReadGuard<'_, T>> {
+        let guard = self.rwlock.read();
+        self.poison.result(PoisoningRwLockIrqSafeReadGuard { guard })
+    }
+
+    /// Tries to lock this rwlock with shared read access without spinning.
+    ///
+    /// Returns [`TryLockError::WouldBlock`] if it is locked for writing,
+    /// or [`TryLockError::Poisoned`] containing the guard if the lock is poisoned.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_read(&self) -> TryLockResult<PoisoningRwLockIrqSafeReadGuard<'_, T>> {
+        let guard = self.rwlock.try_read().ok_or(TryLockError::WouldBlock)?;
+        Ok(self.poison.result(PoisoningRwLockIrqSafeReadGuard { guard })?)
+    }
+
+    /// Locks this rwlock with exclusive write access, holding interrupts, and returns a guard.
+    ///
+    /// Returns an `Err` containing the guard if the lock is poisoned.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn write(&self) -> LockResult<PoisoningRwLockIrqSafeWriteGuard<'_, T>> {
+        let guard = self.rwlock.write();
+        self.poison.result(PoisoningRwLockIrqSafeWriteGuard {
+            rwlock: self,
+            poison: self.poison.guard(),
+            guard,
+        })
+    }
+
+    /// Tries to lock this rwlock with exclusive write access without spinning.
+    ///
+    /// Returns [`TryLockError::WouldBlock`] if it is already locked,
+    /// or [`TryLockError::Poisoned`] containing the guard if the lock is poisoned.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_write(&self) -> TryLockResult<PoisoningRwLockIrqSafeWriteGuard<'_, T>> {
+        let guard = self.rwlock.try_write().ok_or(TryLockError::WouldBlock)?;
+        Ok(self.poison.result(PoisoningRwLockIrqSafeWriteGuard {
+            rwlock: self,
+            poison: self.poison.guard(),
+            guard,
+        })?)
+    }
+
+    /// Returns `true` if the lock is poisoned.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clears the poisoned state of the lock,
+    /// e.g., after the protected data has been restored to a consistent state.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// Returns a mutable reference to the underlying data,
+    /// or an `Err` containing it if the lock is poisoned.
+    ///
+    /// Since this call borrows the lock mutably, no actual locking needs to take place.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.poison.result(self.rwlock.get_mut())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PoisoningRwLockIrqSafe<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rwlock.try_read() {
+            Some(guard) => write!(f, "PoisoningRwLockIrqSafe {{ data: {:?}, poisoned: {} }}", &*guard, self.poison.get()),
+            None => write!(f, "PoisoningRwLockIrqSafe {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default> Default for PoisoningRwLockIrqSafe<T> {
+    fn default() -> PoisoningRwLockIrqSafe<T> {
+        PoisoningRwLockIrqSafe::new(Default::default())
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for PoisoningRwLockIrqSafeReadGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for PoisoningRwLockIrqSafeWriteGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'rwlock, T: ?Sized> DerefMut for PoisoningRwLockIrqSafeWriteGuard<'rwlock, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for PoisoningRwLockIrqSafeWriteGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        // This runs before `self.guard` releases the lock.
+        self.rwlock.poison.done(&self.poison);
+    }
+}
@@ -0,0 +1,229 @@
+//! A read-copy-update cell whose read sections are delimited by holding interrupts.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+use spin::Mutex;
+use crate::held_interrupts::{HeldInterrupts, hold_interrupts, without_interrupts};
+use crate::interrupts_restored::MAX_CPUS;
+
+/// The current grace period, incremented whenever a value is retired.
+static GRACE_PERIOD: AtomicUsize = AtomicUsize::new(0);
+/// The number of CPUs that must report quiescent states, or `0` if not yet specified.
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// The latest grace period that each CPU has observed in a quiescent state.
+static QUIESCENT: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+/// Retired values that may still be accessed by readers.
+static RETIRED: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+/// A retired value, which can be dropped once every CPU has observed `grace_period`.
+struct Retired {
+    grace_period: usize,
+    ptr: *mut (),
+    drop: unsafe fn(*mut ()),
+}
+
+// SAFETY: only `Send` values are retired, and they are no longer accessed by readers once dropped.
+unsafe impl Send for Retired {}
+
+unsafe fn drop_box<T>(ptr: *mut ()) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+/// Sets the number of CPUs that must report quiescent states before retired values are dropped.
+///
+/// # Panics
+///
+/// Panics if `count` is greater than [`MAX_CPUS`].
+pub fn set_rcu_cpu_count(count: usize) {
+    assert!(count <= MAX_CPUS, "RCU CPU count {} exceeds MAX_CPUS", count);
+    CPU_COUNT.store(count, Ordering::SeqCst);
+}
+
+/// Reports that the given `cpu` is in a quiescent state,
+/// then drops any retired values that no CPU can still be reading.
+///
+/// The OS must only invoke this while interrupts are enabled on `cpu`,
+/// e.g., from its scheduler or idle loop, and never while an [`RcuReadGuard`] is held.
+///
+/// # Panics
+///
+/// Panics if `cpu` is not less than [`MAX_CPUS`].
+pub fn rcu_quiescent_state(cpu: usize) {
+    QUIESCENT[cpu].store(GRACE_PERIOD.load(Ordering::SeqCst), Ordering::SeqCst);
+    reclaim();
+}
+
+/// Returns the latest grace period that all CPUs have observed in a quiescent state.
+fn completed_grace_period() -> Option<usize> {
+    match CPU_COUNT.load(Ordering::SeqCst) {
+        0 => None,
+        count => QUIESCENT[..count].iter().map(|q| q.load(Ordering::SeqCst)).min(),
+    }
+}
+
+/// Drops all retired values whose grace period has completed.
+fn reclaim() {
+    let Some(completed) = completed_grace_period() else { return };
+    let ready: Vec<Retired> = without_interrupts(|| {
+        let mut retired = RETIRED.lock();
+        if retired.iter().all(|r| r.grace_period > completed) {
+            return Vec::new();
+        }
+        let (ready, pending) = retired.drain(..).partition(|r| r.grace_period <= completed);
+        *retired = pending;
+        ready
+    });
+    // Drop the retired values outside of the lock, with interrupts in their prior state.
+    for r in ready {
+        // SAFETY: `r.ptr` was created from a `Box` of the type that `r.drop` expects,
+        // and no reader can still access it since its grace period has completed.
+        unsafe { (r.drop)(r.ptr) };
+    }
+}
+
+/// A cell whose current value can be read from any context with interrupts held,
+/// and replaced by writers without blocking readers.
+///
+/// # Reclamation
+///
+/// Readers hold interrupts while they access the current value,
+/// so a CPU cannot be within a read section while interrupts are enabled.
+/// Writers atomically publish a new boxed value and retire the old one,
+/// which is only dropped after every CPU has passed through a point where interrupts
+/// were enabled, i.e., a quiescent state, as reported by the OS via [`rcu_quiescent_state()`].
+///
+/// Before reclamation can happen, the OS must specify how many CPUs
+/// must report quiescent states via [`set_rcu_cpu_count()`].
+/// Until then, retired values are kept.
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::RcuCell;
+///
+/// let config = RcuCell::new([1, 2, 3]);
+/// {
+///     let current = config.read();
+///     // Interrupts are now held, and `current` will remain valid until it is dropped.
+///     assert_eq!(current[0], 1);
+/// }
+/// config.update([4, 5, 6]);
+/// assert_eq!(config.read()[0], 4);
+/// ```
+pub struct RcuCell<T> {
+    ptr: AtomicPtr<T>,
+    _owns: PhantomData<Box<T>>,
+}
+
+// Readers on multiple CPUs share `&T`, and values are dropped on whichever CPU reclaims them.
+unsafe impl<T: Send + Sync> Sync for RcuCell<T> {}
+unsafe impl<T: Send> Send for RcuCell<T> {}
+
+/// A guard that gives access to the value of an [`RcuCell`] at the time it was read.
+///
+/// Interrupts are held until the guard is dropped, which keeps the value from being reclaimed.
+pub struct RcuReadGuard<'a, T> {
+    value: &'a T,
+    _held_irq: HeldInterrupts,
+}
+
+impl<T> RcuCell<T> {
+    /// Creates a new `RcuCell` with the given initial value.
+    pub fn new(value: T) -> RcuCell<T> {
+        RcuCell {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            _owns: PhantomData,
+        }
+    }
+
+    /// Holds interrupts and returns a guard for the current value.
+    #[inline]
+    pub fn read(&self) -> RcuReadGuard<'_, T> {
+        let _held_irq = hold_interrupts();
+        // SAFETY: the value is not reclaimed until this CPU reports a quiescent state,
+        // which it cannot do while interrupts are held.
+        let value = unsafe { &*self.ptr.load(Ordering::Acquire) };
+        RcuReadGuard { value, _held_irq }
+    }
+
+    /// Holds interrupts and invokes `f` with the current value, returning its result.
+    #[inline]
+    pub fn with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&*self.read())
+    }
+
+    /// Returns a mutable reference to the current value.
+    ///
+    /// Since this call borrows the [`RcuCell`] mutably, no readers can exist.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        // SAFETY: the current value is owned by this cell.
+        unsafe { &mut *self.ptr.load(Ordering::Relaxed) }
+    }
+
+    /// Consumes this `RcuCell`, returning its current value.
+    pub fn into_inner(self) -> T {
+        let ptr = self.ptr.swap(ptr::null_mut(), Ordering::Relaxed);
+        // SAFETY: the current value is owned by this cell, which will not drop it again.
+        *unsafe { Box::from_raw(ptr) }
+    }
+}
+
+impl<T: Send + 'static> RcuCell<T> {
+    /// Publishes `value` as the new value of this cell.
+    ///
+    /// The old value is dropped once every CPU has passed through a quiescent state.
+    pub fn update(&self, value: T) {
+        self.update_boxed(Box::new(value));
+    }
+
+    /// Publishes the already-boxed `value` as the new value of this cell.
+    ///
+    /// The old value is dropped once every CPU has passed through a quiescent state.
+    pub fn update_boxed(&self, value: Box<T>) {
+        let old = self.ptr.swap(Box::into_raw(value), Ordering::AcqRel);
+        let grace_period = GRACE_PERIOD.fetch_add(1, Ordering::SeqCst) + 1;
+        without_interrupts(|| RETIRED.lock().push(Retired {
+            grace_period,
+            ptr: old as *mut (),
+            drop: drop_box::<T>,
+        }));
+        reclaim();
+    }
+}
+
+impl<T> Drop for RcuCell<T> {
+    fn drop(&mut self) {
+        let ptr = *self.ptr.get_mut();
+        if !ptr.is_null() {
+            // SAFETY: the current value is owned by this cell, and no readers can exist.
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RcuCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_read(|data| write!(f, "RcuCell {{ data: {:?} }}", data))
+    }
+}
+
+impl<T: Default> Default for RcuCell<T> {
+    fn default() -> RcuCell<T> {
+        RcuCell::new(Default::default())
+    }
+}
+
+impl<'a, T> Deref for RcuReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
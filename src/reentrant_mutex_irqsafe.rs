@@ -0,0 +1,198 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
+use crate::per_cpu::CpuId;
+
+/// The value of `owner` when no CPU holds the lock.
+const NO_OWNER: usize = usize::MAX;
+
+/// A spinlock that holds interrupts and can be re-acquired by the CPU that already holds it.
+///
+/// This is useful for locks that may be re-entered on the same CPU,
+/// e.g., a console lock used by both a logger and the panic handler.
+/// Because nested acquisitions alias each other, the guard only gives shared access (`&T`);
+/// use a [`Cell`](core::cell::Cell) or [`RefCell`](core::cell::RefCell) for mutation.
+///
+/// Interrupts are only held once, by the outermost acquisition on a CPU,
+/// which ensures the owning task cannot migrate to another CPU while it holds the lock.
+/// As with [`HeldInterrupts`], nested guards should be dropped in the reverse order
+/// of their acquisition; otherwise, interrupts are restored while an inner guard is still live.
+/// The owning CPU is determined by the user-supplied [`CpuId`] implementation `C`.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::cell::RefCell;
+/// use irq_safety::{CpuId, ReentrantMutexIrqSafe};
+///
+/// struct MyCpuId;
+/// unsafe impl CpuId for MyCpuId {
+///     fn current_cpu() -> usize { 0 /* read from hardware */ }
+/// }
+///
+/// static CONSOLE: ReentrantMutexIrqSafe<RefCell<usize>, MyCpuId> =
+///     ReentrantMutexIrqSafe::new(RefCell::new(0));
+///
+/// let outer = CONSOLE.lock();
+/// // The same CPU can lock it again without deadlocking.
+/// let inner = CONSOLE.lock();
+/// *inner.borrow_mut() += 1;
+/// drop(inner);
+/// assert_eq!(*outer.borrow(), 1);
+/// ```
+pub struct ReentrantMutexIrqSafe<T: ?Sized, C: CpuId> {
+    /// The CPU that currently holds the lock, or `NO_OWNER`.
+    owner: AtomicUsize,
+    /// The number of nested acquisitions by the owning CPU.
+    /// This is only accessed by the owning CPU.
+    count: UnsafeCell<usize>,
+    _cpu_id: PhantomData<fn() -> C>,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that gives shared access to the data protected by a [`ReentrantMutexIrqSafe`].
+///
+/// When the outermost guard on a CPU falls out of scope, it will release the lock
+/// and restore interrupts to their prior state.
+pub struct ReentrantMutexIrqSafeGuard<'a, T: ?Sized + 'a, C: CpuId> {
+    mutex: &'a ReentrantMutexIrqSafe<T, C>,
+    // `_held_irq` will be dropped after the lock is released in `Drop::drop()`.
+    // It is only `Some` for the outermost acquisition.
+    _held_irq: Option<HeldInterrupts>,
+}
+
+impl<'a, T: ?Sized, C: CpuId> !Send for ReentrantMutexIrqSafeGuard<'a, T, C> {}
+
+// Nested acquisitions only ever happen on the owning CPU, which `CpuId` guarantees is unique,
+// so the data is never accessed by multiple CPUs at once.
+unsafe impl<T: ?Sized + Send, C: CpuId> Sync for ReentrantMutexIrqSafe<T, C> {}
+unsafe impl<T: ?Sized + Send, C: CpuId> Send for ReentrantMutexIrqSafe<T, C> {}
+
+impl<T, C: CpuId> ReentrantMutexIrqSafe<T, C> {
+    /// Creates a new reentrant spinlock wrapping the supplied data.
+    pub const fn new(data: T) -> ReentrantMutexIrqSafe<T, C> {
+        ReentrantMutexIrqSafe {
+            owner: AtomicUsize::new(NO_OWNER),
+            count: UnsafeCell::new(0),
+            _cpu_id: PhantomData,
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this `ReentrantMutexIrqSafe`, returning the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized, C: CpuId> ReentrantMutexIrqSafe<T, C> {
+    /// Locks the spinlock and returns a guard, blocking until it can be acquired.
+    ///
+    /// If the current CPU already holds the lock, this returns immediately.
+    #[inline(always)]
+    pub fn lock(&self) -> ReentrantMutexIrqSafeGuard<'_, T, C> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Tries to lock the spinlock. If it is held by another CPU, it will return None.
+    /// Otherwise it returns a guard within Some.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<ReentrantMutexIrqSafeGuard<'_, T, C>> {
+        let held_irq = hold_interrupts();
+        let cpu = C::current_cpu();
+        if self.owner.load(Ordering::Relaxed) == cpu {
+            // SAFETY: only the owning CPU accesses `count`, and interrupts are held,
+            // so no interrupt handler on this CPU can access it concurrently.
+            unsafe { *self.count.get() += 1 };
+            // Interrupts are already held by the outermost guard, so `held_irq` is a no-op.
+            return Some(ReentrantMutexIrqSafeGuard { mutex: self, _held_irq: None });
+        }
+        self.owner
+            .compare_exchange(NO_OWNER, cpu, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| {
+                // SAFETY: this CPU just became the owner.
+                unsafe { *self.count.get() = 1 };
+                ReentrantMutexIrqSafeGuard { mutex: self, _held_irq: Some(held_irq) }
+            })
+    }
+
+    /// Locks the spinlock and invokes `f` with the protected data, returning its result.
+    #[inline]
+    pub fn with<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&*self.lock())
+    }
+
+    /// Attempts to lock the spinlock and, if successful, invokes `f` with the protected data.
+    #[inline]
+    pub fn try_with<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        self.try_lock().map(|guard| f(&*guard))
+    }
+
+    /// Returns `true` if the lock is currently held by any CPU.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.owner.load(Ordering::Relaxed) != NO_OWNER
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`ReentrantMutexIrqSafe`] mutably, no actual locking needs to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, C: CpuId> fmt::Debug for ReentrantMutexIrqSafe<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "ReentrantMutexIrqSafe {{ data: {:?} }}", &*guard),
+            None => write!(f, "ReentrantMutexIrqSafe {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default, C: CpuId> Default for ReentrantMutexIrqSafe<T, C> {
+    fn default() -> ReentrantMutexIrqSafe<T, C> {
+        ReentrantMutexIrqSafe::new(Default::default())
+    }
+}
+
+impl<'a, T: ?Sized, C: CpuId> Deref for ReentrantMutexIrqSafeGuard<'a, T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: this CPU holds the lock, and all guards only give shared access.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized, C: CpuId> Drop for ReentrantMutexIrqSafeGuard<'a, T, C> {
+    fn drop(&mut self) {
+        // SAFETY: this CPU holds the lock, and interrupts are still held.
+        unsafe {
+            let count = &mut *self.mutex.count.get();
+            *count -= 1;
+            if *count == 0 {
+                self.mutex.owner.store(NO_OWNER, Ordering::Release);
+            }
+        }
+    }
+}
@@ -1,12 +1,75 @@
 use core::{fmt, ops::{Deref, DerefMut}};
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(loom))]
 use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(loom)]
+use crate::loom_backend::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
 
 /// A simple wrapper around a `RwLock` whose guards disable interrupts properly 
+///
+/// By default, new readers can acquire the lock while writers are waiting;
+/// see [`RwLockIrqSafe::with_fairness()`] for other policies.
 pub struct RwLockIrqSafe<T: ?Sized> {
+    #[cfg(feature = "deadlock_detection")]
+    writer: crate::deadlock_detection::Owner,
+    #[cfg(feature = "lock_registry")]
+    registration: crate::lock_registry::Registration,
+    fairness: RwLockFairness,
+    /// The number of writers waiting in `write()`.
+    /// Unless the lock is reader-preferring, new readers back off while this is non-zero.
+    pending_writers: AtomicUsize,
+    /// Incremented whenever a write guard is dropped, for phase-fairness.
+    write_phase: AtomicUsize,
+    /// The number of readers that have waited through an entire write phase.
+    /// If the lock is phase-fair, new writers back off while this is non-zero.
+    priority_readers: AtomicUsize,
     rwlock: RwLock<T>,
 }
 
+/// The policy that decides whether readers or writers first acquire a contended [`RwLockIrqSafe`].
+///
+/// Under the non-default policies, a writer waiting in [`RwLockIrqSafe::write()`]
+/// (or a phase-fair reader with priority waiting in [`RwLockIrqSafe::read()`])
+/// holds interrupts until it acquires the lock, such that interrupt handlers on its CPU
+/// cannot wait for it forever.
+/// Also, a CPU that already holds a read guard must not recursively invoke
+/// [`RwLockIrqSafe::read()`] on the same lock, as that deadlocks
+/// if a writer started waiting in between.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RwLockFairness {
+    /// New readers may acquire the lock while writers are waiting,
+    /// so a steady stream of readers can starve writers. This is the default.
+    #[default]
+    ReaderPreferring,
+    /// New readers back off while any writer is waiting in [`RwLockIrqSafe::write()`],
+    /// so a steady stream of writers can starve readers.
+    WriterPreferring,
+    /// Like `WriterPreferring`, except that readers that have waited through
+    /// an entire write phase acquire the lock before the next writer,
+    /// so neither readers nor writers can be starved.
+    PhaseFair,
+}
+
+/// Increments a count of waiters, which is decremented when dropped.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(count: &'a AtomicUsize) -> Waiting<'a> {
+        count.fetch_add(1, Ordering::Relaxed);
+        Waiting(count)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 
 /// A guard to which the protected data can be read
 ///
@@ -14,6 +77,8 @@ pub struct RwLockIrqSafe<T: ?Sized> {
 /// potentially releasing the lock and potentially re-enabling interrupts.
 pub struct RwLockIrqSafeReadGuard<'a, T: 'a + ?Sized> {
     guard: RwLockReadGuard<'a, T>,
+    #[cfg(feature = "sim")]
+    _live: crate::sim::LiveGuard,
     // `_held_irq` will be dropped after `guard`.
     // Rust guarantees that fields are dropped in the order of declaration.
     _held_irq: HeldInterrupts,
@@ -23,7 +88,15 @@ pub struct RwLockIrqSafeReadGuard<'a, T: 'a + ?Sized> {
 ///
 /// When the guard falls out of scope it will release the lock and potentially re-enable interrupts.
 pub struct RwLockIrqSafeWriteGuard<'a, T: 'a + ?Sized> {
+    rwlock: &'a RwLockIrqSafe<T>,
+    // Whether the lock was forcibly taken from its previous holders.
+    broken: bool,
+    // `_owner` must be dropped before `guard` releases the lock.
+    #[cfg(feature = "deadlock_detection")]
+    _owner: crate::deadlock_detection::OwnerRecord<'a>,
     guard: RwLockWriteGuard<'a, T>,
+    #[cfg(feature = "sim")]
+    _live: crate::sim::LiveGuard,
     // `_held_irq` will be dropped after `guard`.
     // Rust guarantees that fields are dropped in the order of declaration.
     _held_irq: HeldInterrupts,
@@ -49,8 +122,60 @@ impl<T> RwLockIrqSafe<T> {
     /// }
     /// ```
     #[inline]
+    #[cfg(not(loom))]
     pub const fn new(data: T) -> RwLockIrqSafe<T> {
+        RwLockIrqSafe::with_fairness(data, RwLockFairness::ReaderPreferring)
+    }
+
+    /// Creates a new spinlock wrapping the supplied data.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[inline]
+    #[cfg(loom)]
+    pub fn new(data: T) -> RwLockIrqSafe<T> {
+        RwLockIrqSafe::with_fairness(data, RwLockFairness::ReaderPreferring)
+    }
+
+    /// Creates a new spinlock wrapping the supplied data,
+    /// which uses the given `fairness` policy when both readers and writers are waiting.
+    ///
+    /// ```no_run
+    /// use irq_safety::{RwLockFairness, RwLockIrqSafe};
+    ///
+    /// static STATS: RwLockIrqSafe<u64> = RwLockIrqSafe::with_fairness(0, RwLockFairness::PhaseFair);
+    /// ```
+    #[inline]
+    #[cfg(not(loom))]
+    pub const fn with_fairness(data: T, fairness: RwLockFairness) -> RwLockIrqSafe<T> {
+        RwLockIrqSafe {
+            #[cfg(feature = "deadlock_detection")]
+            writer: crate::deadlock_detection::Owner::new(),
+            #[cfg(feature = "lock_registry")]
+            registration: crate::lock_registry::Registration::new(),
+            fairness,
+            pending_writers: AtomicUsize::new(0),
+            write_phase: AtomicUsize::new(0),
+            priority_readers: AtomicUsize::new(0),
+            rwlock: RwLock::new(data),
+        }
+    }
+
+    /// Creates a new spinlock wrapping the supplied data,
+    /// which uses the given `fairness` policy when both readers and writers are waiting.
+    ///
+    /// Under `loom`, this cannot be a `const fn`.
+    #[inline]
+    #[cfg(loom)]
+    pub fn with_fairness(data: T, fairness: RwLockFairness) -> RwLockIrqSafe<T> {
         RwLockIrqSafe {
+            #[cfg(feature = "deadlock_detection")]
+            writer: crate::deadlock_detection::Owner::new(),
+            #[cfg(feature = "lock_registry")]
+            registration: crate::lock_registry::Registration::new(),
+            fairness,
+            pending_writers: AtomicUsize::new(0),
+            write_phase: AtomicUsize::new(0),
+            priority_readers: AtomicUsize::new(0),
             rwlock: RwLock::new(data),
         }
     }
@@ -84,12 +209,51 @@ impl<T: ?Sized> RwLockIrqSafe<T> {
     /// }
     /// ```
     #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
     pub fn read<'a>(&'a self) -> RwLockIrqSafeReadGuard<'a, T> {
+        // The write phase in which this reader started waiting, and whether it has
+        // since waited through an entire write phase, for phase-fairness.
+        let mut start_phase = None;
+        // Once this reader has priority, it holds interrupts until it acquires the lock,
+        // such that writers in interrupt handlers on this CPU cannot back off for it forever.
+        let mut priority: Option<(HeldInterrupts, Waiting)> = None;
         loop {
-            match self.try_read() {
-                Some(guard) => return guard,
-                _ => {}
+            priority = match priority {
+                None => match self.try_read() {
+                    Some(guard) => return guard,
+                    None => None,
+                },
+                Some((held_irq, waiting)) if self.may_read(true) => match self.try_read_held(held_irq) {
+                    Ok(guard) => return guard,
+                    Err(held_irq) => Some((held_irq, waiting)),
+                },
+                priority => priority,
+            };
+            if self.fairness == RwLockFairness::PhaseFair && priority.is_none() {
+                let phase = self.write_phase.load(Ordering::Relaxed);
+                match start_phase {
+                    None => start_phase = Some(phase),
+                    Some(start) if start != phase => {
+                        priority = Some((hold_interrupts(), Waiting::new(&self.priority_readers)));
+                    }
+                    _ => {}
+                }
             }
+            #[cfg(loom)]
+            loom::hint::spin_loop();
+            #[cfg(feature = "deadlock_detection")]
+            self.writer.check(
+                self as *const _ as *const () as usize,
+                "RwLockIrqSafe",
+                core::panic::Location::caller(),
+            );
+            #[cfg(feature = "sim")]
+            crate::sim::on_spin(
+                self as *const _ as *const () as usize,
+                "RwLockIrqSafe",
+                crate::sim::Access::Shared,
+                core::panic::Location::caller(),
+            );
         }
     }
 
@@ -116,15 +280,67 @@ impl<T: ?Sized> RwLockIrqSafe<T> {
     /// }
     /// ```
     #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
     pub fn try_read(&self) -> Option<RwLockIrqSafeReadGuard<T>> {
-        if self.rwlock.writer_count() > 0 { return None; }
-        let _held_irq = hold_interrupts();
-        self.rwlock.try_read().map(|guard| RwLockIrqSafeReadGuard {
+        if !self.may_read(false) { return None; }
+        self.try_read_held(hold_interrupts()).ok()
+    }
+
+    /// Returns `true` if a reader may currently try to acquire this lock,
+    /// i.e., it is not write-locked and, unless the reader has `priority`,
+    /// no writers are waiting that it must back off for.
+    #[inline]
+    fn may_read(&self, priority: bool) -> bool {
+        if self.rwlock.writer_count() > 0 { return false; }
+        priority
+            || self.fairness == RwLockFairness::ReaderPreferring
+            || self.pending_writers.load(Ordering::Relaxed) == 0
+    }
+
+    /// Attempts to acquire this lock with shared read access,
+    /// moving `_held_irq` into the returned guard or returning it on failure.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    fn try_read_held(&self, _held_irq: HeldInterrupts) -> Result<RwLockIrqSafeReadGuard<'_, T>, HeldInterrupts> {
+        let Some(guard) = self.rwlock.try_read() else { return Err(_held_irq) };
+        Ok(RwLockIrqSafeReadGuard {
             guard,
+            #[cfg(feature = "sim")]
+            _live: crate::sim::LiveGuard::new(
+                self as *const _ as *const () as usize,
+                "RwLockIrqSafe",
+                crate::sim::Access::Shared,
+                core::panic::Location::caller(),
+            ),
             _held_irq,
         })
     }
 
+    /// Locks this rwlock with shared read access and invokes `f` with the protected data,
+    /// returning its result.
+    ///
+    /// The read access is released and interrupts are restored as soon as `f` returns.
+    ///
+    /// ```no_run
+    /// let mylock = irq_safety::RwLockIrqSafe::new(5);
+    /// assert_eq!(mylock.with_read(|data| *data * 2), 10);
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&*self.read())
+    }
+
+    /// Attempts to lock this rwlock with shared read access and,
+    /// if successful, invokes `f` with the protected data.
+    ///
+    /// Returns `None` without invoking `f` if read access could not be granted.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_with_read<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        self.try_read().map(|guard| f(&*guard))
+    }
+
     /// Return the number of readers that currently hold the lock (including upgradable readers).
     ///
     /// # Safety
@@ -185,12 +401,41 @@ impl<T: ?Sized> RwLockIrqSafe<T> {
     /// }
     /// ```
     #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
     pub fn write<'a>(&'a self) -> RwLockIrqSafeWriteGuard<'a, T> {
+        // Once this writer is pending, it holds interrupts until it acquires the lock,
+        // such that readers in interrupt handlers on this CPU cannot back off for it forever.
+        let mut pending: Option<(HeldInterrupts, Waiting)> = None;
         loop {
-            match self.try_write() {
-                Some(guard) => return guard,
-                _ => {}
+            pending = match pending {
+                None => match self.try_write() {
+                    Some(guard) => return guard,
+                    None => None,
+                },
+                Some((held_irq, waiting)) if self.may_write() => match self.try_write_held(held_irq) {
+                    Ok(guard) => return guard,
+                    Err(held_irq) => Some((held_irq, waiting)),
+                },
+                pending => pending,
+            };
+            if self.fairness != RwLockFairness::ReaderPreferring && pending.is_none() {
+                pending = Some((hold_interrupts(), Waiting::new(&self.pending_writers)));
             }
+            #[cfg(loom)]
+            loom::hint::spin_loop();
+            #[cfg(feature = "deadlock_detection")]
+            self.writer.check(
+                self as *const _ as *const () as usize,
+                "RwLockIrqSafe",
+                core::panic::Location::caller(),
+            );
+            #[cfg(feature = "sim")]
+            crate::sim::on_spin(
+                self as *const _ as *const () as usize,
+                "RwLockIrqSafe",
+                crate::sim::Access::Exclusive,
+                core::panic::Location::caller(),
+            );
         }
     }
 
@@ -214,17 +459,121 @@ impl<T: ?Sized> RwLockIrqSafe<T> {
     /// }
     /// ```
     #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
     pub fn try_write(&self) -> Option<RwLockIrqSafeWriteGuard<T>> {
+        if !self.may_write() { return None; }
+        self.try_write_unfair()
+    }
+
+    /// Returns `true` if a writer may currently try to acquire this lock,
+    /// i.e., it is unlocked and no priority readers are waiting that it must back off for.
+    #[inline]
+    fn may_write(&self) -> bool {
         if self.rwlock.writer_count() > 0 || self.rwlock.reader_count() > 0 {
-            return None;
+            return false;
         }
-        let _held_irq = hold_interrupts();
-        self.rwlock.try_write().map(|guard| RwLockIrqSafeWriteGuard {
+        self.fairness != RwLockFairness::PhaseFair || self.priority_readers.load(Ordering::Relaxed) == 0
+    }
+
+    /// Attempts to lock this rwlock with exclusive write access, regardless of its fairness policy.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    fn try_write_unfair(&self) -> Option<RwLockIrqSafeWriteGuard<'_, T>> {
+        self.try_write_held(hold_interrupts()).ok()
+    }
+
+    /// Attempts to lock this rwlock with exclusive write access, regardless of its fairness policy,
+    /// moving `_held_irq` into the returned guard or returning it on failure.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    fn try_write_held(&self, _held_irq: HeldInterrupts) -> Result<RwLockIrqSafeWriteGuard<'_, T>, HeldInterrupts> {
+        #[cfg(any(feature = "sim", feature = "deadlock_detection"))]
+        let location = core::panic::Location::caller();
+        let Some(guard) = self.rwlock.try_write() else { return Err(_held_irq) };
+        Ok(RwLockIrqSafeWriteGuard {
+            rwlock: self,
+            broken: false,
+            #[cfg(feature = "deadlock_detection")]
+            _owner: self.writer.acquired(location),
             guard,
+            #[cfg(feature = "sim")]
+            _live: crate::sim::LiveGuard::new(
+                self as *const _ as *const () as usize,
+                "RwLockIrqSafe",
+                crate::sim::Access::Exclusive,
+                location,
+            ),
             _held_irq,
         })
     }
 
+    /// Locks this rwlock with exclusive write access, forcibly taking it from its current
+    /// readers or writer if it cannot be acquired within `spins` attempts.
+    ///
+    /// This is intended for panic handlers and crash-dump paths, where the lock
+    /// may be held by a CPU that will never release it, e.g., the current CPU.
+    /// Whether the lock was taken forcibly can be checked with [`RwLockIrqSafeWriteGuard::is_broken()`].
+    ///
+    /// The lock is broken after a single attempt if [`set_system_panicking()`](crate::set_system_panicking)
+    /// has been invoked or, with the `deadlock_detection` feature enabled,
+    /// if the current CPU is known to hold the lock for writing.
+    ///
+    /// If any previous holders are still running, they may access the data concurrently,
+    /// and dropping their guards will corrupt the lock's state.
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn write_or_break(&self, spins: usize) -> RwLockIrqSafeWriteGuard<'_, T> {
+        let spins = if crate::lock_break::is_system_panicking() { 0 } else { spins };
+        #[cfg(feature = "deadlock_detection")]
+        let spins = if self.writer.held_by_current_cpu() { 0 } else { spins };
+
+        // Even without waiting, a free lock is taken normally rather than broken.
+        for _ in 0..spins.max(1) {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+        loop {
+            // SAFETY: the caller accepts that the previous holders are presumed to never release the lock.
+            unsafe {
+                while self.rwlock.reader_count() > 0 {
+                    self.rwlock.force_read_decrement();
+                }
+                self.rwlock.force_write_unlock();
+            }
+            if let Some(mut guard) = self.try_write_unfair() {
+                guard.broken = true;
+                return guard;
+            }
+        }
+    }
+
+    /// Locks this rwlock with exclusive write access and invokes `f` with the protected data,
+    /// returning its result.
+    ///
+    /// The write access is released and interrupts are restored as soon as `f` returns.
+    ///
+    /// ```no_run
+    /// let mylock = irq_safety::RwLockIrqSafe::new(0);
+    /// mylock.with_write(|data| *data += 1);
+    /// assert_eq!(mylock.with_read(|data| *data), 1);
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn with_write<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut *self.write())
+    }
+
+    /// Attempts to lock this rwlock with exclusive write access and,
+    /// if successful, invokes `f` with the protected data.
+    ///
+    /// Returns `None` without invoking `f` if write access could not be granted.
+    #[inline]
+    #[cfg_attr(any(feature = "sim", feature = "deadlock_detection"), track_caller)]
+    pub fn try_with_write<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        self.try_write().map(|mut guard| f(&mut *guard))
+    }
+
     /// Returns a mutable reference to the underlying data.
     ///
     /// Since this call borrows the [`RwLockIrqSafe`] mutably, and a mutable reference is guaranteed to be exclusive in Rust,
@@ -244,6 +593,41 @@ impl<T: ?Sized> RwLockIrqSafe<T> {
     }
 }
 
+impl<T: ?Sized> RwLockIrqSafe<T> {
+    /// Returns the fairness policy of this rwlock.
+    #[inline(always)]
+    pub fn fairness(&self) -> RwLockFairness {
+        self.fairness
+    }
+}
+
+#[cfg(feature = "lock_registry")]
+impl<T: Send + Sync + 'static> RwLockIrqSafe<T> {
+    /// Adds this lock to the global registry under the given `name`,
+    /// such that it is listed by [`dump_held_locks()`](crate::dump_held_locks) while held.
+    ///
+    /// Registering a lock again has no effect; it keeps its original name.
+    pub fn register(&'static self, name: &'static str) {
+        self.registration.register(name, self);
+    }
+}
+
+#[cfg(feature = "lock_registry")]
+impl<T: Send + Sync> crate::lock_registry::RegisteredLock for RwLockIrqSafe<T> {
+    fn is_held(&self) -> bool {
+        self.reader_count() != 0 || self.writer_count() != 0
+    }
+
+    fn describe(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        let writers = self.writer_count();
+        let state = if writers != 0 { "write-locked" } else { "read-locked" };
+        write!(w, "RwLockIrqSafe {}, {} readers, {} writers", state, self.reader_count(), writers)?;
+        #[cfg(feature = "deadlock_detection")]
+        self.writer.describe(w)?;
+        Ok(())
+    }
+}
+
 impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockIrqSafe<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.rwlock.try_read() {
@@ -280,3 +664,22 @@ impl<'rwlock, T: ?Sized> DerefMut for RwLockIrqSafeWriteGuard<'rwlock, T> {
         &mut *(self.guard)
     }
 }
+
+impl<'rwlock, T: ?Sized> RwLockIrqSafeWriteGuard<'rwlock, T> {
+    /// Returns `true` if this guard was obtained by [`RwLockIrqSafe::write_or_break()`]
+    /// forcibly taking the lock from its previous holders.
+    ///
+    /// This is an associated function rather than a method,
+    /// to avoid conflicting with methods on the protected data.
+    pub fn is_broken(s: &Self) -> bool {
+        s.broken
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for RwLockIrqSafeWriteGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        if self.rwlock.fairness == RwLockFairness::PhaseFair {
+            self.rwlock.write_phase.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
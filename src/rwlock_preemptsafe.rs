@@ -0,0 +1,27 @@
+use crate::held_preemption::HeldPreemption;
+use crate::holding_rwlock::{HoldingRwLock, HoldingRwLockReadGuard, HoldingRwLockWriteGuard};
+
+/// A simple wrapper around a `RwLock` whose guards disable preemption properly.
+///
+/// This is like [`RwLockIrqSafe`](crate::RwLockIrqSafe), except that it leaves interrupts enabled.
+/// Thus, it must only be used for data that is shared between tasks,
+/// never with interrupt handlers or deferred handlers.
+///
+/// # Example
+///
+/// ```no_run
+/// let rwlock = irq_safety::RwLockPreemptSafe::new(0);
+/// {
+///     let mut data = rwlock.write();
+///     // Preemption is now disabled, but interrupts are not.
+///     *data = 2;
+/// }
+/// assert_eq!(*rwlock.read(), 2);
+/// ```
+pub type RwLockPreemptSafe<T> = HoldingRwLock<T, HeldPreemption>;
+
+/// A guard to which the data protected by a [`RwLockPreemptSafe`] can be read.
+pub type RwLockPreemptSafeReadGuard<'a, T> = HoldingRwLockReadGuard<'a, T, HeldPreemption>;
+
+/// A guard to which the data protected by a [`RwLockPreemptSafe`] can be written.
+pub type RwLockPreemptSafeWriteGuard<'a, T> = HoldingRwLockWriteGuard<'a, T, HeldPreemption>;
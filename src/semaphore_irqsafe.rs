@@ -0,0 +1,168 @@
+use core::{
+    fmt,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use crate::held_interrupts::{HeldInterrupts, hold_interrupts};
+
+/// A counting semaphore whose permits hold interrupts while they exist.
+///
+/// This is intended for cases where an interrupt handler signals work
+/// by releasing permits via [`SemaphoreIrqSafe::release()`],
+/// which never blocks, and regular threads consume that work by acquiring permits.
+///
+/// # Example
+///
+/// ```no_run
+/// use irq_safety::SemaphoreIrqSafe;
+///
+/// static PENDING_PACKETS: SemaphoreIrqSafe = SemaphoreIrqSafe::new(0);
+///
+/// // Invoked from the NIC's interrupt handler.
+/// fn on_receive_interrupt() {
+///     PENDING_PACKETS.release();
+/// }
+///
+/// fn packet_thread() {
+///     loop {
+///         // Consume one unit of work; it is not returned to the semaphore.
+///         PENDING_PACKETS.acquire().forget();
+///         // handle one packet ...
+///     }
+/// }
+/// ```
+pub struct SemaphoreIrqSafe {
+    permits: AtomicUsize,
+}
+
+/// An RAII guard representing one or more permits acquired from a [`SemaphoreIrqSafe`].
+///
+/// Interrupts are held for as long as this guard exists.
+/// When the guard falls out of scope, its permits are returned to the semaphore
+/// and interrupts are restored to their prior state.
+pub struct SemaphoreIrqSafePermit<'a> {
+    semaphore: &'a SemaphoreIrqSafe,
+    count: usize,
+    // `_held_irq` will be dropped after the permits are returned in `drop()`.
+    _held_irq: HeldInterrupts,
+}
+
+impl SemaphoreIrqSafe {
+    /// Creates a new semaphore with the given number of available permits.
+    pub const fn new(permits: usize) -> SemaphoreIrqSafe {
+        SemaphoreIrqSafe {
+            permits: AtomicUsize::new(permits),
+        }
+    }
+
+    /// Acquires a single permit, spinning until one is available.
+    #[inline]
+    pub fn acquire(&self) -> SemaphoreIrqSafePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Acquires `n` permits at once, spinning until they are all available.
+    #[inline]
+    pub fn acquire_many(&self, n: usize) -> SemaphoreIrqSafePermit<'_> {
+        loop {
+            if let Some(permit) = self.try_acquire_many(n) {
+                return permit;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Attempts to acquire a single permit without blocking.
+    #[inline]
+    pub fn try_acquire(&self) -> Option<SemaphoreIrqSafePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Attempts to acquire `n` permits at once without blocking.
+    ///
+    /// Returns `None` if fewer than `n` permits are currently available.
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphoreIrqSafePermit<'_>> {
+        if self.permits.load(Ordering::Relaxed) < n { return None; }
+        let _held_irq = hold_interrupts();
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| permits.checked_sub(n))
+            .ok()
+            .map(|_| SemaphoreIrqSafePermit {
+                semaphore: self,
+                count: n,
+                _held_irq,
+            })
+    }
+
+    /// Adds a single permit to this semaphore.
+    ///
+    /// This never blocks and does not touch the interrupt state,
+    /// so it is safe to call from an interrupt handler.
+    #[inline]
+    pub fn release(&self) {
+        self.release_many(1)
+    }
+
+    /// Adds `n` permits to this semaphore.
+    ///
+    /// This never blocks and does not touch the interrupt state,
+    /// so it is safe to call from an interrupt handler.
+    #[inline]
+    pub fn release_many(&self, n: usize) {
+        self.permits.fetch_add(n, Ordering::Release);
+    }
+
+    /// Returns the number of permits that are currently available.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline]
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
+
+impl<'a> SemaphoreIrqSafePermit<'a> {
+    /// Returns the number of permits held by this guard.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Consumes this guard without returning its permits to the semaphore.
+    ///
+    /// Interrupts are still restored to their prior state.
+    #[inline]
+    pub fn forget(mut self) {
+        self.count = 0;
+    }
+}
+
+impl<'a> Drop for SemaphoreIrqSafePermit<'a> {
+    fn drop(&mut self) {
+        let count = mem::take(&mut self.count);
+        if count > 0 {
+            self.semaphore.release_many(count);
+        }
+    }
+}
+
+impl Default for SemaphoreIrqSafe {
+    fn default() -> SemaphoreIrqSafe {
+        SemaphoreIrqSafe::new(0)
+    }
+}
+
+impl fmt::Debug for SemaphoreIrqSafe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SemaphoreIrqSafe {{ permits: {} }}", self.available_permits())
+    }
+}
+
+impl<'a> fmt::Debug for SemaphoreIrqSafePermit<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SemaphoreIrqSafePermit {{ count: {} }}", self.count)
+    }
+}
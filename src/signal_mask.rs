@@ -0,0 +1,172 @@
+//! A backend for hosted Unix environments that treats signals as interrupts.
+//!
+//! Instead of disabling hardware interrupts, "holding interrupts" blocks
+//! a configurable set of signals for the current thread via `pthread_sigmask`.
+//! This allows [`MutexIrqSafe`](crate::MutexIrqSafe) and [`RwLockIrqSafe`](crate::RwLockIrqSafe)
+//! to protect data shared between threads and signal handlers in user space.
+
+use core::{
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicU64, Ordering, compiler_fence},
+};
+use libc::c_int;
+
+/// The set of signals that are blocked when interrupts are held.
+///
+/// Bit `n - 1` represents signal number `n`.
+static MASKED_SIGNALS: AtomicU64 = AtomicU64::new(DEFAULT_MASKED_SIGNALS);
+
+/// By default, every signal is masked except for those that are raised synchronously
+/// by faults (which cannot be meaningfully deferred) and those that cannot be blocked.
+const DEFAULT_MASKED_SIGNALS: u64 = !(
+    bit(libc::SIGSEGV) | bit(libc::SIGBUS) | bit(libc::SIGFPE) | bit(libc::SIGILL)
+        | bit(libc::SIGTRAP) | bit(libc::SIGKILL) | bit(libc::SIGSTOP)
+);
+
+const fn bit(signal: c_int) -> u64 {
+    1 << (signal - 1)
+}
+
+/// Sets the signals that are blocked when interrupts are held or disabled.
+///
+/// By default, all signals are blocked except for `SIGSEGV`, `SIGBUS`, `SIGFPE`,
+/// `SIGILL`, and `SIGTRAP`, which are raised synchronously by faults,
+/// and `SIGKILL` and `SIGSTOP`, which cannot be blocked.
+///
+/// This should be called once at startup, before any interrupts are held;
+/// existing [`HeldInterrupts`](crate::HeldInterrupts) guards are not affected.
+///
+/// # Panics
+///
+/// Panics if any signal number is not within `1..=64`.
+pub fn set_masked_signals(signals: &[c_int]) {
+    let mask = signals.iter().fold(0, |mask, &signal| {
+        assert!((1..=64).contains(&signal), "invalid signal number {}", signal);
+        mask | bit(signal)
+    });
+    MASKED_SIGNALS.store(mask, Ordering::SeqCst);
+}
+
+/// Returns the given set of signals, in the same format as [`MASKED_SIGNALS`], as a `sigset_t`.
+fn sigset(signals: u64) -> libc::sigset_t {
+    let mut set = MaybeUninit::<libc::sigset_t>::uninit();
+    unsafe {
+        libc::sigemptyset(set.as_mut_ptr());
+        for signal in 1..=64 {
+            if signals & bit(signal) != 0 {
+                // Signal numbers not supported on this platform are rejected, which is fine.
+                libc::sigaddset(set.as_mut_ptr(), signal);
+            }
+        }
+        set.assume_init()
+    }
+}
+
+/// Returns the current set of masked signals as a `sigset_t`.
+fn masked_sigset() -> libc::sigset_t {
+    sigset(MASKED_SIGNALS.load(Ordering::SeqCst))
+}
+
+fn sigmask(how: c_int, set: Option<&libc::sigset_t>) -> libc::sigset_t {
+    let mut old = MaybeUninit::<libc::sigset_t>::uninit();
+    let set = set.map_or(ptr::null(), |s| s as *const _);
+    let ret = unsafe { libc::pthread_sigmask(how, set, old.as_mut_ptr()) };
+    assert_eq!(ret, 0, "pthread_sigmask failed");
+    unsafe { old.assume_init() }
+}
+
+/// The masked signals that were unblocked before a [`HeldInterrupts`](crate::HeldInterrupts)
+/// guard was created, which are unblocked again when it is dropped.
+pub(crate) struct SavedMask(u64);
+
+/// Blocks the masked signals, returning those that were previously unblocked.
+pub(crate) fn block_signals() -> SavedMask {
+    let masked = MASKED_SIGNALS.load(Ordering::SeqCst);
+    let old = sigmask(libc::SIG_BLOCK, Some(&sigset(masked)));
+    compiler_fence(Ordering::SeqCst);
+    let unblocked = (1..=64)
+        .filter(|&signal| masked & bit(signal) != 0)
+        .filter(|&signal| unsafe { libc::sigismember(&old, signal) } == 0)
+        .fold(0, |unblocked, signal| unblocked | bit(signal));
+    SavedMask(unblocked)
+}
+
+/// Unblocks the signals that [`block_signals()`] blocked.
+pub(crate) fn restore_signals(saved: &SavedMask) {
+    compiler_fence(Ordering::SeqCst);
+    if unblocks_any(saved) {
+        sigmask(libc::SIG_UNBLOCK, Some(&sigset(saved.0)));
+    }
+}
+
+/// Returns `true` if restoring the `saved` mask would unblock any of the masked signals,
+/// i.e., re-enable "interrupts".
+#[inline(always)]
+pub(crate) fn unblocks_any(saved: &SavedMask) -> bool {
+    saved.0 != 0
+}
+
+/// Unblocks the masked signals for the current thread.
+#[inline(always)]
+pub fn enable_interrupts() {
+    compiler_fence(Ordering::SeqCst);
+    sigmask(libc::SIG_UNBLOCK, Some(&masked_sigset()));
+}
+
+/// Blocks the masked signals for the current thread.
+#[inline(always)]
+pub fn disable_interrupts() {
+    sigmask(libc::SIG_BLOCK, Some(&masked_sigset()));
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Returns `true` if none of the masked signals are currently blocked for the current thread.
+#[inline(always)]
+pub fn interrupts_enabled() -> bool {
+    let current = sigmask(libc::SIG_BLOCK, None);
+    let mask = MASKED_SIGNALS.load(Ordering::SeqCst);
+    (1..=64)
+        .filter(|&signal| mask & bit(signal) != 0)
+        .all(|signal| unsafe { libc::sigismember(&current, signal) } != 1)
+}
+
+/// Returns a copy of `mask` with the given `signals` removed.
+fn without_signals(mut mask: libc::sigset_t, signals: u64) -> libc::sigset_t {
+    for signal in (1..=64).filter(|&signal| signals & bit(signal) != 0) {
+        unsafe { libc::sigdelset(&mut mask, signal) };
+    }
+    mask
+}
+
+/// Atomically unblocks the masked signals and waits for a signal to be handled.
+///
+/// The masked signals remain unblocked when this returns.
+pub fn enable_interrupts_and_halt() {
+    enable_interrupts_and_wait();
+    enable_interrupts();
+}
+
+/// Atomically unblocks the masked signals and waits for a signal to be handled,
+/// then blocks the masked signals again.
+pub fn enable_interrupts_and_wait() {
+    let current = sigmask(libc::SIG_BLOCK, None);
+    compiler_fence(Ordering::SeqCst);
+    // `sigsuspend` always returns -1 with `EINTR` once a signal handler has run.
+    let masked = MASKED_SIGNALS.load(Ordering::SeqCst);
+    unsafe { libc::sigsuspend(&without_signals(current, masked)) };
+    disable_interrupts();
+}
+
+/// Atomically unblocks the `saved` signals and waits for a signal to be handled,
+/// after which the current signal mask is reinstated.
+///
+/// Returns immediately if `saved` does not unblock any of the masked signals.
+pub(crate) fn wait_with_mask(saved: &SavedMask) {
+    if unblocks_any(saved) {
+        let current = sigmask(libc::SIG_BLOCK, None);
+        compiler_fence(Ordering::SeqCst);
+        unsafe { libc::sigsuspend(&without_signals(current, saved.0)) };
+        compiler_fence(Ordering::SeqCst);
+    }
+}
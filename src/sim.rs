@@ -0,0 +1,250 @@
+//! A simulated interrupt backend with interrupt injection, for testing.
+//!
+//! With the `sim` feature enabled, each thread acts as its own simulated CPU
+//! with its own interrupt-enabled flag, which starts out enabled.
+//! Holding or disabling interrupts merely clears that flag.
+//!
+//! Test code can register fake interrupt handlers via [`register_irq_handler()`].
+//! These handlers are fired on the current thread at every point where an interrupt
+//! could arrive on real hardware, i.e., whenever interrupts are enabled via
+//! [`enable_interrupts()`] (including when a [`HeldInterrupts`](crate::HeldInterrupts)
+//! guard re-enables them upon being dropped), and right before interrupts
+//! that are currently enabled get disabled.
+//! Like real interrupt handlers, they run with interrupts disabled and are never nested.
+//!
+//! If an injected handler spins on an irq-safe lock that is held by a live guard
+//! on the same thread, this is a guaranteed deadlock, so it panics immediately
+//! with a report of the live guards. An injected handler that spins for longer
+//! than the limit set by [`set_spin_limit()`] also panics with the same report.
+//!
+//! This feature takes precedence over the `unix_signals` feature.
+
+use core::{
+    cell::{Cell, RefCell},
+    fmt::Write,
+    panic::Location,
+    sync::atomic::{compiler_fence, Ordering},
+};
+use std::{rc::Rc, string::String, vec::Vec};
+
+/// Whether a lock is acquired for shared or exclusive access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Shared,
+    Exclusive,
+}
+
+/// A record of a lock guard that is currently live on this simulated CPU.
+struct GuardRecord {
+    id: u64,
+    lock: usize,
+    kind: &'static str,
+    access: Access,
+    location: &'static Location<'static>,
+}
+
+std::thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+    static IN_IRQ: Cell<bool> = const { Cell::new(false) };
+    static HANDLERS: RefCell<Vec<Rc<dyn Fn()>>> = const { RefCell::new(Vec::new()) };
+    static INJECTED: Cell<usize> = const { Cell::new(0) };
+    static SPINS: Cell<usize> = const { Cell::new(0) };
+    static SPIN_LIMIT: Cell<usize> = const { Cell::new(1_000_000) };
+    static LIVE_GUARDS: RefCell<Vec<GuardRecord>> = const { RefCell::new(Vec::new()) };
+    static NEXT_GUARD_ID: Cell<u64> = const { Cell::new(0) };
+    static TRACKING: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Registers a fake interrupt handler on the current thread's simulated CPU.
+pub fn register_irq_handler<F: Fn() + 'static>(handler: F) {
+    HANDLERS.with(|h| h.borrow_mut().push(Rc::new(handler)));
+}
+
+/// Removes all fake interrupt handlers from the current thread's simulated CPU.
+pub fn clear_irq_handlers() {
+    HANDLERS.with(|h| h.borrow_mut().clear());
+}
+
+/// Sets how many times an injected handler may spin on a lock
+/// before it is considered deadlocked. The default is one million.
+pub fn set_spin_limit(limit: usize) {
+    SPIN_LIMIT.with(|l| l.set(limit));
+}
+
+/// Returns how many times interrupts have been injected on the current thread.
+pub fn injected_irq_count() -> usize {
+    INJECTED.with(Cell::get)
+}
+
+/// Returns `true` if the current thread is running an injected interrupt handler.
+pub fn in_irq() -> bool {
+    IN_IRQ.with(Cell::get)
+}
+
+/// Enables simulated interrupts on the current thread, then fires all registered handlers.
+#[inline(always)]
+pub fn enable_interrupts() {
+    compiler_fence(Ordering::SeqCst);
+    ENABLED.with(|e| e.set(true));
+    inject();
+}
+
+/// Fires all registered handlers if interrupts are currently enabled,
+/// then disables simulated interrupts on the current thread.
+#[inline(always)]
+pub fn disable_interrupts() {
+    if interrupts_enabled() {
+        inject();
+    }
+    ENABLED.with(|e| e.set(false));
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Enables simulated interrupts on the current thread and fires all registered handlers,
+/// as if the CPU had been woken up by an interrupt.
+pub fn enable_interrupts_and_halt() {
+    enable_interrupts();
+}
+
+/// Fires all registered handlers as if the CPU had been woken up by an interrupt,
+/// leaving simulated interrupts disabled on the current thread.
+pub fn enable_interrupts_and_wait() {
+    compiler_fence(Ordering::SeqCst);
+    ENABLED.with(|e| e.set(true));
+    inject();
+    ENABLED.with(|e| e.set(false));
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Returns whether simulated interrupts are enabled on the current thread.
+#[inline(always)]
+pub fn interrupts_enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// Runs all registered handlers as if an interrupt had arrived.
+fn inject() {
+    // While unwinding, e.g., from a deadlock report, guards that are dropped re-enable interrupts;
+    // injecting again could panic within that drop, which would abort the whole test binary.
+    if in_irq() || std::thread::panicking() {
+        return;
+    }
+    // Enter the interrupt context before cloning the handlers, as that may allocate,
+    // which holds interrupts if the global allocator is an `IrqSafeAllocator`.
+    IN_IRQ.with(|i| i.set(true));
+    ENABLED.with(|e| e.set(false));
+    // Restore the interrupt context even if a handler panics,
+    // such that the panic can be caught and inspected by the test.
+    struct ExitIrq;
+    impl Drop for ExitIrq {
+        fn drop(&mut self) {
+            SPINS.with(|s| s.set(0));
+            ENABLED.with(|e| e.set(true));
+            IN_IRQ.with(|i| i.set(false));
+        }
+    }
+    let _exit = ExitIrq;
+    // The handlers cannot be accessed if this was reached by allocating while modifying them,
+    // e.g., in `register_irq_handler()`, or by deallocating them when the thread exits.
+    let Ok(Ok(handlers)) = HANDLERS.try_with(|h| h.try_borrow().map(|h| h.clone())) else { return };
+    if handlers.is_empty() {
+        return;
+    }
+    INJECTED.with(|i| i.set(i.get() + 1));
+    for handler in handlers {
+        handler();
+    }
+}
+
+/// Invokes `f` without tracking the lock guards that it creates,
+/// e.g., for a lock that must not allocate while it is held.
+pub(crate) fn untracked<R, F: FnOnce() -> R>(f: F) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TRACKING.with(|t| t.set(self.0));
+        }
+    }
+    let _restore = Restore(TRACKING.with(|t| t.replace(false)));
+    f()
+}
+
+/// The id of a guard that is not tracked.
+const UNTRACKED: u64 = u64::MAX;
+
+/// Tracks a live lock guard for deadlock reports; unregisters itself when dropped.
+pub(crate) struct LiveGuard(u64);
+
+impl LiveGuard {
+    pub(crate) fn new(
+        lock: usize,
+        kind: &'static str,
+        access: Access,
+        location: &'static Location<'static>,
+    ) -> LiveGuard {
+        if !TRACKING.with(Cell::get) {
+            return LiveGuard(UNTRACKED);
+        }
+        let id = NEXT_GUARD_ID.with(|n| {
+            let id = n.get();
+            n.set(id + 1);
+            id
+        });
+        LIVE_GUARDS.with(|g| g.borrow_mut().push(GuardRecord { id, lock, kind, access, location }));
+        LiveGuard(id)
+    }
+}
+
+impl Drop for LiveGuard {
+    fn drop(&mut self) {
+        if self.0 == UNTRACKED {
+            return;
+        }
+        let _ = LIVE_GUARDS.try_with(|g| g.borrow_mut().retain(|r| r.id != self.0));
+    }
+}
+
+/// Invoked on every failed iteration of a lock's spin loop.
+pub(crate) fn on_spin(lock: usize, kind: &'static str, access: Access, location: &'static Location<'static>) {
+    if !in_irq() {
+        return;
+    }
+    let self_deadlock = LIVE_GUARDS.with(|g| g.borrow().iter().any(|r|
+        r.lock == lock && (r.access == Access::Exclusive || access == Access::Exclusive)
+    ));
+    let spins = SPINS.with(|s| {
+        s.set(s.get() + 1);
+        s.get()
+    });
+    if self_deadlock || spins > SPIN_LIMIT.with(Cell::get) {
+        panic!("{}", deadlock_report(lock, kind, access, location, self_deadlock));
+    }
+}
+
+fn deadlock_report(
+    lock: usize,
+    kind: &'static str,
+    access: Access,
+    location: &'static Location<'static>,
+    self_deadlock: bool,
+) -> String {
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "deadlock detected: injected IRQ handler is spinning on {} {:#x} ({:?} access) at {}{}",
+        kind, lock, access, location,
+        if self_deadlock { ", which is held on this CPU" } else { ", spin limit exceeded" },
+    );
+    let _ = writeln!(report, "live guards on this CPU:");
+    LIVE_GUARDS.with(|g| {
+        for r in g.borrow().iter() {
+            let _ = writeln!(
+                report,
+                "  {} {:#x} ({:?} access) acquired at {}{}",
+                r.kind, r.lock, r.access, r.location,
+                if r.lock == lock { "  <-- contended" } else { "" },
+            );
+        }
+    });
+    report
+}
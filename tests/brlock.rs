@@ -0,0 +1,71 @@
+//! Tests for reading and writing a `BrLockIrqSafe` from multiple CPUs.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{interrupts_enabled, BrLockIrqSafe, CpuId};
+use std::{cell::Cell, thread};
+
+thread_local! {
+    static CPU: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Reports the CPU id chosen by the current thread, which is `0` by default.
+struct ThreadCpu;
+// SAFETY: each thread that accesses a lock concurrently with others chooses a unique id.
+unsafe impl CpuId for ThreadCpu {
+    fn current_cpu() -> usize { CPU.with(Cell::get) }
+}
+
+fn spawn_on_cpu<F: FnOnce() + Send + 'static>(cpu: usize, f: F) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        CPU.with(|c| c.set(cpu));
+        f()
+    })
+}
+
+#[test]
+fn readers_and_writers_exclude_each_other() {
+    let brlock: BrLockIrqSafe<usize, 4, ThreadCpu> = BrLockIrqSafe::new(0);
+    {
+        let readers = (brlock.read(), brlock.read());
+        assert!(!interrupts_enabled());
+        assert_eq!(brlock.reader_count(), 2);
+        assert!(brlock.try_write().is_none());
+        drop(readers);
+    }
+    assert!(interrupts_enabled());
+    {
+        let mut writer = brlock.write();
+        assert!(!interrupts_enabled());
+        assert_eq!(brlock.writer_count(), 1);
+        assert!(brlock.try_read().is_none());
+        assert_eq!(format!("{:?}", brlock), "BrLockIrqSafe { <locked> }");
+        *writer += 1;
+    }
+    assert!(interrupts_enabled());
+    assert_eq!(brlock.writer_count(), 0);
+    assert_eq!(format!("{:?}", brlock), "BrLockIrqSafe { data: 1 }");
+    assert_eq!(brlock.into_inner(), 1);
+}
+
+#[test]
+fn waiting_writer_blocks_new_readers_on_other_cpus() {
+    static BRLOCK: BrLockIrqSafe<usize, 4, ThreadCpu> = BrLockIrqSafe::new(0);
+
+    CPU.with(|c| c.set(1));
+    let reader = BRLOCK.read();
+    let writer = spawn_on_cpu(2, || *BRLOCK.write() += 1);
+    // Once the writer is waiting, readers on other CPUs are turned away.
+    spawn_on_cpu(3, || {
+        while BRLOCK.writer_count() == 0 || BRLOCK.try_read().is_some() {
+            thread::yield_now();
+        }
+    }).join().unwrap();
+    // The waiting writer must not block a nested read on this CPU, as that would deadlock.
+    let nested = BRLOCK.try_read().unwrap();
+    assert_eq!(*nested + *reader, 0);
+    drop(nested);
+    drop(reader);
+    writer.join().unwrap();
+    assert_eq!(BRLOCK.with_read(|data| *data), 1);
+}
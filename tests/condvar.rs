@@ -0,0 +1,88 @@
+//! Tests for waiting on a `CondvarIrqSafe`.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{CondvarIrqSafe, MutexIrqSafe, Parker};
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+
+#[cfg(feature = "sim")]
+#[test]
+fn handler_notifies_a_waiting_thread() {
+    use irq_safety::sim;
+    static DONE: MutexIrqSafe<bool> = MutexIrqSafe::new(false);
+    static CONDVAR: CondvarIrqSafe = CondvarIrqSafe::new();
+    static WAITING: AtomicBool = AtomicBool::new(false);
+    static NOTIFIED: AtomicUsize = AtomicUsize::new(0);
+
+    sim::register_irq_handler(|| {
+        if WAITING.load(Ordering::SeqCst) && NOTIFIED.load(Ordering::SeqCst) == 0 {
+            *DONE.lock() = true;
+            CONDVAR.notify_one();
+            NOTIFIED.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    // The handler fires once the wait restores interrupts, i.e., after the condition was checked.
+    let done = CONDVAR.wait_while(DONE.lock(), |done| {
+        WAITING.store(true, Ordering::SeqCst);
+        !*done
+    });
+    assert!(*done);
+    assert_eq!(NOTIFIED.load(Ordering::SeqCst), 1);
+    drop(done);
+    sim::clear_irq_handlers();
+}
+
+/// Whether the waiters that are blocked in `GatedParker::park()` may return.
+static OPEN: AtomicBool = AtomicBool::new(false);
+/// The number of invocations of `GatedParker::park()`.
+static PARKED: AtomicUsize = AtomicUsize::new(0);
+
+/// Blocks waiters until `OPEN` is set, even if a wakeup is pending.
+struct GatedParker;
+
+impl Parker for GatedParker {
+    fn park(_key: usize, should_park: &dyn Fn() -> bool) {
+        PARKED.fetch_add(1, Ordering::SeqCst);
+        while !OPEN.load(Ordering::SeqCst) || should_park() {
+            thread::yield_now();
+        }
+    }
+
+    fn unpark_one(_key: usize) { }
+
+    fn unpark_all(_key: usize) { }
+}
+
+#[test]
+fn notify_all_discards_tokens_from_notify_one() {
+    static LOCK: MutexIrqSafe<()> = MutexIrqSafe::new(());
+    static CONDVAR: CondvarIrqSafe<GatedParker> = CondvarIrqSafe::new();
+    static NOTIFIED: AtomicBool = AtomicBool::new(false);
+
+    let waiters: Vec<_> = (0..2).map(|_| thread::spawn(|| drop(CONDVAR.wait(LOCK.lock())))).collect();
+    while PARKED.load(Ordering::SeqCst) < 2 {
+        thread::yield_now();
+    }
+    // Both waiters are woken up by `notify_all()`, so neither consumes the token.
+    CONDVAR.notify_one();
+    CONDVAR.notify_all();
+    OPEN.store(true, Ordering::SeqCst);
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+
+    // A later waiter must not be woken up by the leftover token.
+    let notifier = thread::spawn(|| {
+        while PARKED.load(Ordering::SeqCst) < 3 {
+            thread::yield_now();
+        }
+        NOTIFIED.store(true, Ordering::SeqCst);
+        CONDVAR.notify_one();
+    });
+    drop(CONDVAR.wait(LOCK.lock()));
+    assert!(NOTIFIED.load(Ordering::SeqCst));
+    notifier.join().unwrap();
+}
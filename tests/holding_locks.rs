@@ -0,0 +1,56 @@
+//! Tests for the locks that hold off softirqs or preemption instead of interrupts.
+//!
+//! No controllers are registered, so these locks fall back to holding interrupts.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{interrupts_enabled, MutexBhSafe, MutexBhSafeGuard, MutexPreemptSafe, RwLockPreemptSafe};
+
+#[test]
+fn debug_prints_the_public_type_name() {
+    let bh = MutexBhSafe::new(1);
+    let preempt = MutexPreemptSafe::new(2);
+    let rwlock = RwLockPreemptSafe::new(3);
+    assert_eq!(format!("{:?}", bh), "MutexBhSafe { data: 1 }");
+    assert_eq!(format!("{:?}", preempt), "MutexPreemptSafe { data: 2 }");
+    assert_eq!(format!("{:?}", rwlock), "RwLockPreemptSafe { data: 3 }");
+
+    let guard = preempt.lock();
+    assert_eq!(format!("{:?}", preempt), "MutexPreemptSafe { <locked> }");
+    drop(guard);
+    let guard = rwlock.write();
+    assert_eq!(format!("{:?}", rwlock), "RwLockPreemptSafe { <locked> }");
+    drop(guard);
+}
+
+#[test]
+fn rwlock_guards_hold_until_dropped() {
+    let rwlock = RwLockPreemptSafe::new(0);
+    {
+        let readers = (rwlock.read(), rwlock.read());
+        assert!(!interrupts_enabled());
+        assert_eq!(rwlock.reader_count(), 2);
+        assert!(rwlock.try_write().is_none());
+        drop(readers);
+    }
+    assert!(interrupts_enabled());
+    *rwlock.write() += 1;
+    assert!(interrupts_enabled());
+    assert_eq!(rwlock.with_read(|data| *data), 1);
+}
+
+#[test]
+fn unlocked_releases_the_lock_and_the_hold() {
+    let mutex = MutexBhSafe::new(0);
+    let mut guard = mutex.lock();
+    *guard += 1;
+    MutexBhSafeGuard::unlocked(&mut guard, || {
+        assert!(interrupts_enabled());
+        *mutex.try_lock().unwrap() += 1;
+    });
+    assert!(!interrupts_enabled());
+    *guard += 1;
+    assert!(!MutexBhSafeGuard::is_broken(&guard));
+    drop(guard);
+    assert_eq!(*mutex.lock(), 3);
+}
@@ -0,0 +1,49 @@
+//! Tests for handing off items from simulated interrupt handlers through an `IrqChannel`.
+
+#![cfg(feature = "sim")]
+
+use irq_safety::{hold_interrupts, sim, IrqChannel};
+use std::{cell::Cell, rc::Rc};
+
+#[test]
+fn items_pushed_by_a_handler_are_popped_in_order() {
+    static CHANNEL: IrqChannel<usize, 4> = IrqChannel::new();
+    let (producer, mut consumer) = CHANNEL.split_mpsc().unwrap();
+    assert!(CHANNEL.split().is_none());
+
+    let next = Cell::new(0);
+    sim::register_irq_handler(move || {
+        if producer.push(next.get()).is_ok() {
+            next.set(next.get() + 1);
+        }
+    });
+
+    // Each hold injects one handler run, which pushes the next item.
+    let mut expected = 0;
+    while expected < 100 {
+        drop(hold_interrupts());
+        while let Some(item) = consumer.pop() {
+            assert_eq!(item, expected);
+            expected += 1;
+        }
+    }
+    assert_eq!(CHANNEL.capacity(), 4);
+}
+
+#[test]
+fn full_channel_returns_the_item_and_drops_the_rest() {
+    let channel: IrqChannel<Rc<()>, 2> = IrqChannel::new();
+    let item = Rc::new(());
+    {
+        let (mut producer, mut consumer) = channel.split().unwrap();
+        assert!(producer.push(item.clone()).is_ok());
+        assert!(producer.push(item.clone()).is_ok());
+        assert!(producer.is_full());
+        assert!(producer.push(item.clone()).is_err());
+        assert!(consumer.pop().is_some());
+        assert!(producer.push(item.clone()).is_ok());
+    }
+    assert_eq!(Rc::strong_count(&item), 3);
+    drop(channel);
+    assert_eq!(Rc::strong_count(&item), 1);
+}
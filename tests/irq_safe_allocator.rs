@@ -0,0 +1,80 @@
+//! Tests for `IrqSafeAllocator`, installed as this test binary's global allocator.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+use irq_safety::{InnerAllocator, IrqSafeAllocator};
+use std::alloc::{GlobalAlloc, Layout, System};
+
+/// An unusual size, such that allocations of it can be told apart from the test harness's.
+const MARKER_SIZE: usize = 12345;
+
+/// Forwards to the system allocator, counting the live allocations of `MARKER_SIZE` bytes.
+struct CountingSystem {
+    live_markers: usize,
+}
+
+unsafe impl InnerAllocator for CountingSystem {
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        if layout.size() == MARKER_SIZE {
+            self.live_markers += 1;
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        if layout.size() == MARKER_SIZE {
+            self.live_markers -= 1;
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: IrqSafeAllocator<CountingSystem> = IrqSafeAllocator::new(CountingSystem { live_markers: 0 });
+
+fn live_markers() -> usize {
+    ALLOCATOR.lock().live_markers
+}
+
+#[test]
+fn global_allocations_go_through_the_wrapper() {
+    let before = live_markers();
+    let marker = Vec::<u8>::with_capacity(MARKER_SIZE);
+    assert_eq!(live_markers(), before + 1);
+    drop(marker);
+    assert_eq!(live_markers(), before);
+}
+
+#[cfg(feature = "sim")]
+#[test]
+fn simulated_handlers_can_allocate() {
+    use irq_safety::{hold_interrupts, sim};
+    use std::{cell::RefCell, rc::Rc};
+
+    let allocated = Rc::new(RefCell::new(Vec::new()));
+    let a = allocated.clone();
+    sim::register_irq_handler(move || a.borrow_mut().push(Box::new([0u8; 64])));
+    for _ in 0..10 {
+        let held = hold_interrupts();
+        let _ = Box::new([0u8; 64]);
+        drop(held);
+    }
+    assert_eq!(allocated.borrow().len(), sim::injected_irq_count());
+    sim::clear_irq_handlers();
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn allocator_api_goes_through_the_wrapper() {
+    let before = live_markers();
+    let mut marker = Vec::<u8, _>::with_capacity_in(MARKER_SIZE, &ALLOCATOR);
+    assert_eq!(live_markers(), before + 1);
+    marker.push(1);
+    drop(marker);
+    assert_eq!(live_markers(), before);
+
+    // Zero-sized allocations do not reach the inner allocator.
+    let empty = Vec::<(), _>::with_capacity_in(16, &ALLOCATOR);
+    assert_eq!(empty.capacity(), usize::MAX);
+}
@@ -0,0 +1,66 @@
+//! Tests for writing to a console through an `IrqSafeWriter`.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use core::fmt::{self, Write};
+use irq_safety::{interrupts_enabled, IrqSafeWriter, MutexIrqSafe};
+
+/// Collects the written output, recording whether any of it was written with interrupts enabled.
+#[derive(Default)]
+struct Console {
+    output: String,
+    written_with_interrupts_enabled: bool,
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.written_with_interrupts_enabled |= interrupts_enabled();
+        self.output.push_str(s);
+        Ok(())
+    }
+}
+
+#[test]
+fn writes_hold_the_console_lock() {
+    let console = MutexIrqSafe::new(Console::default());
+    let mut writer = IrqSafeWriter::new(&console);
+    let greeting = "Hello";
+    write!(writer, "{}, ", greeting).unwrap();
+    writer.write_str("world").unwrap();
+    writer.write_char('!').unwrap();
+    assert!(interrupts_enabled());
+
+    let console = console.into_inner();
+    assert_eq!(console.output, "Hello, world!");
+    assert!(!console.written_with_interrupts_enabled);
+}
+
+#[test]
+fn force_write_bypasses_a_held_lock() {
+    let console = MutexIrqSafe::new(Console::default());
+    let writer = IrqSafeWriter::new(&console);
+    let guard = console.lock();
+    // The lock is held by this CPU, which will not release it until the write returns.
+    let line = 42;
+    writer.force_write(format_args!("panicked at line {}", line)).unwrap();
+    drop(guard);
+    writer.force_write(format_args!(", and here")).unwrap();
+    assert!(interrupts_enabled());
+
+    let console = console.into_inner();
+    assert_eq!(console.output, "panicked at line 42, and here");
+    assert!(!console.written_with_interrupts_enabled);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_records_are_written_as_lines() {
+    use log::{Level, Log, Record};
+
+    let console = MutexIrqSafe::new(Console::default());
+    let writer = IrqSafeWriter::new(&console);
+    let free = 3;
+    writer.log(&Record::builder().level(Level::Warn).args(format_args!("{} pages left", free)).build());
+    writer.flush();
+    assert_eq!(console.into_inner().output, "[WARN] 3 pages left\n");
+}
@@ -0,0 +1,52 @@
+//! Tests for borrowing the contents of a `LocalIrqCell`.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{interrupts_enabled, LocalIrqCell};
+
+#[test]
+fn borrow_holds_interrupts_until_dropped() {
+    let cell = const { LocalIrqCell::new(0) };
+    {
+        let mut data = cell.borrow_mut();
+        assert!(!interrupts_enabled());
+        assert!(cell.is_borrowed());
+        assert!(cell.try_borrow_mut().is_none());
+        assert_eq!(format!("{:?}", cell), "LocalIrqCell { <borrowed> }");
+        *data += 1;
+    }
+    assert!(interrupts_enabled());
+    assert!(!cell.is_borrowed());
+    assert_eq!(format!("{:?}", cell), "LocalIrqCell { data: 1 }");
+    *cell.try_borrow_mut().unwrap() += 1;
+    assert_eq!(cell.into_inner(), 2);
+}
+
+#[test]
+#[should_panic(expected = "is already borrowed on this CPU")]
+fn nested_borrow_panics() {
+    let cell = LocalIrqCell::new(());
+    let _outer = cell.borrow_mut();
+    let _inner = cell.borrow_mut();
+}
+
+#[cfg(feature = "sim")]
+#[test]
+fn handler_borrows_after_the_thread_releases() {
+    use irq_safety::sim;
+    thread_local! {
+        static COUNTER: LocalIrqCell<usize> = const { LocalIrqCell::new(0) };
+    }
+
+    // The handler runs whenever interrupts are held or restored, but never while the cell is borrowed.
+    sim::register_irq_handler(|| COUNTER.with(|c| *c.borrow_mut() += 1));
+    for _ in 0..5 {
+        COUNTER.with(|c| *c.borrow_mut() += 1);
+    }
+    COUNTER.with(|c| {
+        let count = c.borrow_mut();
+        assert!(sim::injected_irq_count() >= 5);
+        assert_eq!(*count, 5 + sim::injected_irq_count());
+    });
+    sim::clear_irq_handlers();
+}
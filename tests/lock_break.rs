@@ -0,0 +1,60 @@
+//! Tests for forcibly taking held locks in crash paths.
+//!
+//! Marking the system as panicking cannot be undone, so this file contains a single test.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{
+    hold_interrupts, interrupts_enabled, set_system_panicking,
+    MutexIrqSafe, MutexIrqSafeGuard, MutexPreemptSafe, MutexPreemptSafeGuard, RwLockIrqSafe, RwLockIrqSafeWriteGuard,
+};
+
+#[test]
+fn only_held_locks_are_broken() {
+    let mutex = MutexIrqSafe::new(0);
+    let rwlock = RwLockIrqSafe::new(0);
+    let preempt = MutexPreemptSafe::new(0);
+    set_system_panicking();
+
+    // Free locks are taken normally, even without waiting.
+    assert!(!MutexIrqSafeGuard::is_broken(&mutex.lock_or_break(0)));
+    assert!(!RwLockIrqSafeWriteGuard::is_broken(&rwlock.write_or_break(0)));
+    assert!(!MutexPreemptSafeGuard::is_broken(&preempt.lock_or_break(0)));
+
+    let held = mutex.lock();
+    {
+        let mut broken = mutex.lock_or_break(0);
+        assert!(MutexIrqSafeGuard::is_broken(&broken));
+        *broken += 1;
+        // Re-acquiring the lock does not hide that it was broken.
+        MutexIrqSafeGuard::unlocked(&mut broken, || {});
+        assert!(MutexIrqSafeGuard::is_broken(&broken));
+    }
+    drop(held);
+    assert_eq!(*mutex.lock(), 1);
+
+    let held = preempt.lock();
+    {
+        let mut broken = preempt.lock_or_break(0);
+        assert!(MutexPreemptSafeGuard::is_broken(&broken));
+        MutexPreemptSafeGuard::unlocked(&mut broken, || {});
+        assert!(MutexPreemptSafeGuard::is_broken(&broken));
+        *broken += 1;
+    }
+    drop(held);
+    assert_eq!(*preempt.lock(), 1);
+
+    // The read guard is leaked below, so restore interrupts with an outer hold instead.
+    let held_irq = hold_interrupts();
+    let reader = rwlock.read();
+    {
+        let mut broken = rwlock.write_or_break(0);
+        assert!(RwLockIrqSafeWriteGuard::is_broken(&broken));
+        *broken += 1;
+    }
+    // The reader count was already cleared when the lock was broken.
+    std::mem::forget(reader);
+    drop(held_irq);
+    assert!(interrupts_enabled());
+    assert_eq!(*rwlock.read(), 1);
+}
@@ -0,0 +1,61 @@
+//! Tests for dumping the registered locks that are currently held.
+//!
+//! The registry is global, so this file contains a single test.
+
+#![cfg(all(
+    feature = "lock_registry",
+    feature = "deadlock_detection",
+    any(feature = "sim", feature = "unix_signals"),
+))]
+
+use irq_safety::{dump_held_locks, set_deadlock_detection_cpu_id, CpuId, MutexIrqSafe, RwLockIrqSafe};
+
+static MUTEX: MutexIrqSafe<()> = MutexIrqSafe::new(());
+static RWLOCK: RwLockIrqSafe<()> = RwLockIrqSafe::new(());
+
+struct Cpu7;
+// SAFETY: the locks are only used by the single test thread.
+unsafe impl CpuId for Cpu7 {
+    fn current_cpu() -> usize { 7 }
+}
+
+fn dump() -> String {
+    let mut dump = String::new();
+    dump_held_locks(&mut dump).unwrap();
+    dump
+}
+
+#[test]
+fn dump_lists_held_locks_with_their_owners() {
+    set_deadlock_detection_cpu_id::<Cpu7>();
+    MUTEX.register("mutex");
+    // Registering again keeps the original name.
+    MUTEX.register("renamed");
+    RWLOCK.register("rwlock");
+    assert_eq!(dump(), "");
+
+    let (guard, line) = (MUTEX.lock(), line!());
+    assert_eq!(
+        dump(),
+        format!("mutex: MutexIrqSafe locked, owned by CPU 7 (acquired at {}:{}:32)\n", file!(), line),
+    );
+    drop(guard);
+
+    let readers = (RWLOCK.read(), RWLOCK.read());
+    assert_eq!(dump(), "rwlock: RwLockIrqSafe read-locked, 2 readers, 0 writers\n");
+    drop(readers);
+
+    let (mutex_guard, rwlock_guard, line) = (MUTEX.lock(), RWLOCK.write(), line!());
+    // The most recently registered lock is listed first.
+    assert_eq!(
+        dump(),
+        format!(
+            "rwlock: RwLockIrqSafe write-locked, 0 readers, 1 writers, owned by CPU 7 (acquired at {file}:{line}:67)\n\
+             mutex: MutexIrqSafe locked, owned by CPU 7 (acquired at {file}:{line}:52)\n",
+            file = file!(),
+            line = line,
+        ),
+    );
+    drop((mutex_guard, rwlock_guard));
+    assert_eq!(dump(), "");
+}
@@ -0,0 +1,147 @@
+//! Model-checking tests for the lock types.
+//!
+//! Run with `RUSTFLAGS="--cfg loom" cargo test --release --test loom`.
+
+#![cfg(loom)]
+
+use irq_safety::{hold_interrupts, interrupts_enabled, MutexIrqSafe, RwLockFairness, RwLockIrqSafe};
+use loom::{sync::Arc, thread};
+
+#[test]
+fn mutex_lock_unlock() {
+    loom::model(|| {
+        let lock = Arc::new(MutexIrqSafe::new(0));
+        let threads: Vec<_> = (0..2).map(|_| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                let mut guard = lock.lock();
+                assert!(!interrupts_enabled());
+                *guard += 1;
+                drop(guard);
+                assert!(interrupts_enabled());
+            })
+        }).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(*lock.lock(), 2);
+    });
+}
+
+#[test]
+fn mutex_try_lock_is_exclusive() {
+    loom::model(|| {
+        let lock = Arc::new(MutexIrqSafe::new(0));
+        let other = {
+            let lock = lock.clone();
+            thread::spawn(move || lock.try_lock().map(|mut guard| *guard += 1).is_some())
+        };
+        let mine = lock.try_lock().map(|mut guard| *guard += 1).is_some();
+        assert!(interrupts_enabled());
+        let theirs = other.join().unwrap();
+        assert!(mine || theirs);
+        assert_eq!(*lock.lock(), mine as usize + theirs as usize);
+    });
+}
+
+#[test]
+fn rwlock_readers_and_writer() {
+    loom::model(|| {
+        let lock = Arc::new(RwLockIrqSafe::new(0));
+        let reader = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                let value = *lock.read();
+                assert!(value == 0 || value == 1);
+                assert!(interrupts_enabled());
+            })
+        };
+        {
+            let mut guard = lock.write();
+            assert_eq!(lock.writer_count(), 1);
+            *guard += 1;
+        }
+        reader.join().unwrap();
+        assert_eq!(*lock.read(), 1);
+        assert_eq!(lock.writer_count(), 0);
+    });
+}
+
+#[test]
+fn rwlock_try_write_excludes_readers() {
+    loom::model(|| {
+        let lock = Arc::new(RwLockIrqSafe::new(0));
+        let reader = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                if let Some(guard) = lock.try_read() {
+                    assert_eq!(lock.writer_count(), 0);
+                    assert!(*guard == 0 || *guard == 1);
+                }
+            })
+        };
+        if let Some(mut guard) = lock.try_write() {
+            *guard += 1;
+        }
+        reader.join().unwrap();
+    });
+}
+
+/// A reader racing with two writes under the given `fairness` policy.
+fn rwlock_reader_and_writes(fairness: RwLockFairness) {
+    loom::model(move || {
+        let lock = Arc::new(RwLockIrqSafe::with_fairness(0, fairness));
+        let reader = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                let value = *lock.read();
+                assert!(value <= 2);
+                assert!(interrupts_enabled());
+            })
+        };
+        for _ in 0..2 {
+            *lock.write() += 1;
+            assert!(interrupts_enabled());
+        }
+        reader.join().unwrap();
+        assert_eq!(*lock.read(), 2);
+        // Neither a pending writer nor a priority reader is left behind to block new lockers.
+        assert!(lock.try_write().is_some());
+        assert!(lock.try_read().is_some());
+    });
+}
+
+#[test]
+fn rwlock_writer_preferring() {
+    rwlock_reader_and_writes(RwLockFairness::WriterPreferring);
+}
+
+#[test]
+fn rwlock_phase_fair() {
+    rwlock_reader_and_writes(RwLockFairness::PhaseFair);
+}
+
+#[test]
+fn interrupt_state_is_restored_per_thread() {
+    loom::model(|| {
+        let lock = Arc::new(MutexIrqSafe::new(()));
+        let other = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                // This thread starts with interrupts held, which must be preserved.
+                let held = hold_interrupts();
+                drop(lock.lock());
+                assert!(!interrupts_enabled());
+                drop(held);
+                assert!(interrupts_enabled());
+            })
+        };
+        {
+            let _outer = lock.lock();
+            let _inner = hold_interrupts();
+            assert!(!interrupts_enabled());
+        }
+        assert!(interrupts_enabled());
+        other.join().unwrap();
+    });
+}
@@ -0,0 +1,43 @@
+//! Tests for accessing per-CPU data.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{interrupts_enabled, CpuId, PerCpu};
+use std::panic::{self, AssertUnwindSafe};
+
+struct Cpu1;
+// SAFETY: each test only accesses its `PerCpu` from a single thread.
+unsafe impl CpuId for Cpu1 {
+    fn current_cpu() -> usize { 1 }
+}
+
+#[test]
+fn with_accesses_the_current_cpus_instance() {
+    let mut per_cpu: PerCpu<usize, 2, Cpu1> = PerCpu::new([0, 0]);
+    per_cpu.with(|count| {
+        assert!(!interrupts_enabled());
+        *count += 1;
+    });
+    assert!(interrupts_enabled());
+    assert_eq!(per_cpu.iter_mut().map(|count| *count).collect::<Vec<_>>(), [0, 1]);
+}
+
+#[test]
+fn panic_in_with_releases_the_instance() {
+    let per_cpu: PerCpu<usize, 2, Cpu1> = PerCpu::new([0, 0]);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        per_cpu.with(|_| panic!("while accessing the instance"))
+    }));
+    assert!(result.is_err());
+    assert!(interrupts_enabled());
+    assert_eq!(per_cpu.with(|count| { *count += 1; *count }), 1);
+}
+
+#[test]
+fn nested_with_panics() {
+    let per_cpu: PerCpu<usize, 2, Cpu1> = PerCpu::new([0, 0]);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        per_cpu.with(|_| per_cpu.with(|_| ()))
+    }));
+    assert!(result.is_err());
+}
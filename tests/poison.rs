@@ -0,0 +1,60 @@
+//! Tests for lock poisoning, using `std`'s panic state as the panicking hook.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{set_panicking_hook, PoisoningMutexIrqSafe, PoisoningRwLockIrqSafe};
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn panic_while_locked_poisons_mutex() {
+    set_panicking_hook(std::thread::panicking);
+    let mutex = PoisoningMutexIrqSafe::new(0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut guard = mutex.lock().ok().unwrap();
+        *guard += 1;
+        panic!("while holding the lock");
+    }));
+    assert!(result.is_err());
+    assert!(mutex.is_poisoned());
+    // The data is still accessible through the error.
+    assert_eq!(*mutex.lock().err().unwrap().into_inner(), 1);
+
+    mutex.clear_poison();
+    assert!(!mutex.is_poisoned());
+    assert_eq!(*mutex.lock().ok().unwrap(), 1);
+}
+
+#[test]
+fn panic_while_write_locked_poisons_rwlock() {
+    set_panicking_hook(std::thread::panicking);
+    let rwlock = PoisoningRwLockIrqSafe::new(0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut guard = rwlock.write().ok().unwrap();
+        *guard += 1;
+        panic!("while holding the write lock");
+    }));
+    assert!(result.is_err());
+    assert!(rwlock.is_poisoned());
+    assert!(rwlock.write().is_err());
+    assert_eq!(*rwlock.read().err().unwrap().into_inner(), 1);
+
+    rwlock.clear_poison();
+    assert!(!rwlock.is_poisoned());
+    assert_eq!(*rwlock.read().ok().unwrap(), 1);
+}
+
+#[test]
+fn panic_while_read_locked_does_not_poison_rwlock() {
+    set_panicking_hook(std::thread::panicking);
+    let rwlock = PoisoningRwLockIrqSafe::new(0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = rwlock.read().ok().unwrap();
+        panic!("while holding a read lock");
+    }));
+    assert!(result.is_err());
+    assert!(!rwlock.is_poisoned());
+    assert!(rwlock.write().is_ok());
+}
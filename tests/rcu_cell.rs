@@ -0,0 +1,45 @@
+//! Tests for the reclamation of values retired from an `RcuCell`.
+//!
+//! The RCU state is global, so this file contains a single test.
+
+#![cfg(all(feature = "alloc", any(feature = "sim", feature = "unix_signals")))]
+
+use irq_safety::{rcu_quiescent_state, set_rcu_cpu_count, RcuCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct Value(usize);
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(self.0, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn retired_value_is_dropped_after_every_cpu_is_quiescent() {
+    let cell = RcuCell::new(Value(1));
+    cell.update(Value(10));
+    // Nothing is reclaimed before the OS specified how many CPUs must report quiescent states.
+    rcu_quiescent_state(0);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+
+    set_rcu_cpu_count(2);
+    cell.update(Value(100));
+    rcu_quiescent_state(0);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+    {
+        let current = cell.read();
+        assert_eq!(current.0, 100);
+    }
+    rcu_quiescent_state(0);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+
+    // Both retired values are dropped once the last CPU reports a quiescent state.
+    rcu_quiescent_state(1);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1 + 10);
+
+    drop(cell);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1 + 10 + 100);
+}
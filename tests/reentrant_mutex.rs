@@ -0,0 +1,52 @@
+//! Tests for re-acquiring a `ReentrantMutexIrqSafe` on the owning CPU.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{interrupts_enabled, CpuId, ReentrantMutexIrqSafe};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+thread_local! {
+    static CPU: usize = NEXT_CPU.fetch_add(1, Ordering::Relaxed);
+}
+
+struct ThreadCpu;
+// SAFETY: each thread is its own CPU, with a unique id.
+unsafe impl CpuId for ThreadCpu {
+    fn current_cpu() -> usize { CPU.with(|c| *c) }
+}
+
+#[test]
+fn owning_cpu_can_relock() {
+    let mutex: ReentrantMutexIrqSafe<usize, ThreadCpu> = ReentrantMutexIrqSafe::new(1);
+    let outer = mutex.lock();
+    assert!(!interrupts_enabled());
+    let inner = mutex.try_lock().unwrap();
+    assert_eq!(*inner + *outer, 2);
+    assert_eq!(mutex.with(|data| *data), 1);
+    assert_eq!(format!("{:?}", mutex), "ReentrantMutexIrqSafe { data: 1 }");
+
+    // Interrupts stay held and the lock stays owned until the outermost guard is dropped.
+    drop(inner);
+    assert!(!interrupts_enabled());
+    assert!(mutex.is_locked());
+    drop(outer);
+    assert!(interrupts_enabled());
+    assert!(!mutex.is_locked());
+}
+
+#[test]
+fn other_cpus_are_excluded() {
+    static MUTEX: ReentrantMutexIrqSafe<(), ThreadCpu> = ReentrantMutexIrqSafe::new(());
+
+    let guard = MUTEX.lock();
+    thread::spawn(|| {
+        assert!(MUTEX.try_lock().is_none());
+        assert_eq!(format!("{:?}", MUTEX), "ReentrantMutexIrqSafe { <locked> }");
+    }).join().unwrap();
+    drop(guard);
+    thread::spawn(|| assert!(MUTEX.try_lock().is_some())).join().unwrap();
+}
@@ -0,0 +1,42 @@
+//! Tests for acquiring and releasing permits of a `SemaphoreIrqSafe`.
+
+#![cfg(any(feature = "sim", feature = "unix_signals"))]
+
+use irq_safety::{interrupts_enabled, SemaphoreIrqSafe};
+
+#[test]
+fn permits_hold_interrupts_and_are_returned_on_drop() {
+    let semaphore = SemaphoreIrqSafe::new(3);
+    let two = semaphore.acquire_many(2);
+    assert_eq!(two.count(), 2);
+    assert!(!interrupts_enabled());
+    assert!(semaphore.try_acquire_many(2).is_none());
+    let one = semaphore.try_acquire().unwrap();
+    assert_eq!(semaphore.available_permits(), 0);
+    drop(one);
+    drop(two);
+    assert!(interrupts_enabled());
+    assert_eq!(semaphore.available_permits(), 3);
+
+    // Forgotten permits are consumed, but interrupts are still restored.
+    semaphore.acquire().forget();
+    assert!(interrupts_enabled());
+    assert_eq!(semaphore.available_permits(), 2);
+}
+
+#[cfg(feature = "sim")]
+#[test]
+fn handler_releases_permits_to_a_thread() {
+    use irq_safety::{hold_interrupts, sim};
+    static PENDING: SemaphoreIrqSafe = SemaphoreIrqSafe::new(0);
+
+    sim::register_irq_handler(|| PENDING.release());
+    assert!(PENDING.try_acquire().is_none());
+    // Each injected interrupt releases one permit, which the thread consumes.
+    for consumed in 1..=5 {
+        drop(hold_interrupts());
+        PENDING.try_acquire().unwrap().forget();
+        assert_eq!(PENDING.available_permits() + consumed, sim::injected_irq_count());
+    }
+    sim::clear_irq_handlers();
+}
@@ -0,0 +1,289 @@
+//! Tests that inject simulated interrupts at every point where interrupts are enabled.
+
+#![cfg(feature = "sim")]
+
+use irq_safety::{enable_interrupts, hold_interrupts, interrupts_enabled, sim, MutexIrqSafe, RwLockIrqSafe};
+use std::{
+    panic,
+    rc::Rc,
+    cell::Cell,
+};
+
+#[test]
+fn handlers_fire_when_interrupts_are_reenabled() {
+    let fired = Rc::new(Cell::new(0));
+    let f = fired.clone();
+    sim::register_irq_handler(move || {
+        assert!(sim::in_irq());
+        assert!(!interrupts_enabled());
+        f.set(f.get() + 1);
+    });
+
+    let held = hold_interrupts();
+    // One injection right before interrupts were disabled.
+    assert_eq!(fired.get(), 1);
+    {
+        let _nested = hold_interrupts();
+    }
+    // Dropping a nested guard does not re-enable interrupts.
+    assert_eq!(fired.get(), 1);
+    drop(held);
+    assert_eq!(fired.get(), 2);
+    assert!(interrupts_enabled());
+    assert_eq!(sim::injected_irq_count(), 2);
+}
+
+#[test]
+fn wait_for_interrupt_runs_handlers_and_stays_held() {
+    let fired = Rc::new(Cell::new(false));
+    let f = fired.clone();
+    let held = hold_interrupts();
+    sim::register_irq_handler(move || f.set(true));
+
+    let held = held.wait_for_interrupt();
+    assert!(fired.get());
+    assert!(!interrupts_enabled());
+
+    // A nested guard must not enable interrupts while waiting.
+    fired.set(false);
+    let nested = hold_interrupts().wait_for_interrupt();
+    assert!(!fired.get());
+    drop(nested);
+    drop(held);
+    assert!(interrupts_enabled());
+}
+
+#[test]
+fn handler_sharing_a_mutex_never_deadlocks() {
+    static COUNTER: MutexIrqSafe<usize> = MutexIrqSafe::new(0);
+    sim::register_irq_handler(|| *COUNTER.lock() += 1);
+
+    for _ in 0..100 {
+        *COUNTER.lock() += 1;
+    }
+    let injected = sim::injected_irq_count();
+    assert!(injected > 0);
+    sim::clear_irq_handlers();
+    assert_eq!(*COUNTER.lock(), 100 + injected);
+}
+
+#[test]
+fn handler_reading_an_rwlock_while_read_held_does_not_deadlock() {
+    static TABLE: RwLockIrqSafe<usize> = RwLockIrqSafe::new(7);
+    sim::register_irq_handler(|| assert_eq!(*TABLE.read(), 7));
+
+    let guard = TABLE.read();
+    // Erroneously re-enabling interrupts while holding a read guard
+    // still allows other readers to proceed.
+    enable_interrupts();
+    drop(guard);
+}
+
+#[test]
+fn self_deadlock_is_reported_with_live_guard() {
+    static LOCK: MutexIrqSafe<()> = MutexIrqSafe::new(());
+    sim::register_irq_handler(|| drop(LOCK.lock()));
+
+    let result = panic::catch_unwind(|| {
+        let _guard = LOCK.lock();
+        // Erroneously re-enabling interrupts while holding the lock
+        // lets the injected handler spin on it.
+        enable_interrupts();
+    });
+    sim::clear_irq_handlers();
+
+    let payload = result.unwrap_err();
+    let report = payload.downcast_ref::<String>().unwrap();
+    assert!(report.starts_with("deadlock detected"), "{}", report);
+    assert!(report.contains("which is held on this CPU"), "{}", report);
+    assert!(report.contains("<-- contended"), "{}", report);
+    assert!(report.contains(file!()), "{}", report);
+}
+
+#[test]
+fn self_deadlock_within_lock_call_is_catchable() {
+    static LOCK: MutexIrqSafe<()> = MutexIrqSafe::new(());
+    static OTHER: MutexIrqSafe<()> = MutexIrqSafe::new(());
+
+    let result = panic::catch_unwind(|| {
+        let _guard = LOCK.lock();
+        // Erroneously re-enable interrupts before any handler is registered,
+        // such that the handler first fires within `OTHER.lock()`.
+        enable_interrupts();
+        sim::register_irq_handler(|| drop(LOCK.lock()));
+        drop(OTHER.lock());
+    });
+    sim::clear_irq_handlers();
+
+    // Unwinding through `OTHER.lock()` and `_guard` re-enabled interrupts without injecting again.
+    let payload = result.unwrap_err();
+    let report = payload.downcast_ref::<String>().unwrap();
+    assert!(report.starts_with("deadlock detected"), "{}", report);
+    assert!(!LOCK.is_locked());
+    assert!(!OTHER.is_locked());
+    assert!(interrupts_enabled());
+}
+
+#[test]
+fn unlocked_guard_lets_handlers_take_the_lock() {
+    static LOCK: MutexIrqSafe<Vec<&str>> = MutexIrqSafe::new(Vec::new());
+    let mut guard = LOCK.lock();
+    guard.push("thread");
+    sim::register_irq_handler(|| LOCK.lock().push("irq"));
+
+    irq_safety::MutexIrqSafeGuard::unlocked(&mut guard, || assert!(!LOCK.is_locked()));
+    assert!(!interrupts_enabled());
+    guard.push("thread");
+    sim::clear_irq_handlers();
+    assert_eq!(guard.first(), Some(&"thread"));
+    assert_eq!(guard.last(), Some(&"thread"));
+    assert!(guard.contains(&"irq"));
+}
+
+#[test]
+fn unheld_only_enables_interrupts_for_the_outermost_guard() {
+    let mut outer = hold_interrupts();
+    let mut inner = hold_interrupts();
+    inner.unheld(|| assert!(!interrupts_enabled()));
+    drop(inner);
+    outer.unheld(|| assert!(interrupts_enabled()));
+    assert!(!interrupts_enabled());
+}
+
+#[test]
+fn pending_work_runs_when_outermost_guard_is_released() {
+    use irq_safety::{CpuId, InterruptsRestoredHook};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+    static HOOK_RUNS: AtomicUsize = AtomicUsize::new(0);
+    std::thread_local! {
+        static CPU: usize = NEXT_CPU.fetch_add(1, Ordering::Relaxed);
+    }
+    struct ThreadCpu;
+    // SAFETY: each thread is its own simulated CPU, with a unique id.
+    unsafe impl CpuId for ThreadCpu {
+        fn current_cpu() -> usize { CPU.with(|c| *c) }
+    }
+    struct CountRuns;
+    impl InterruptsRestoredHook for CountRuns {
+        fn interrupts_restored() {
+            assert!(interrupts_enabled());
+            HOOK_RUNS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    irq_safety::register_interrupts_restored_hook::<ThreadCpu, CountRuns>();
+    let cpu = ThreadCpu::current_cpu();
+    let outer = hold_interrupts();
+    {
+        let _inner = hold_interrupts();
+        irq_safety::set_pending_work(cpu);
+    }
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 0);
+    assert!(irq_safety::has_pending_work(cpu));
+    drop(outer);
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 1);
+    assert!(!irq_safety::has_pending_work(cpu));
+
+    drop(hold_interrupts());
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 1);
+
+    // Temporarily restoring interrupts runs pending work too.
+    let mut held = hold_interrupts();
+    irq_safety::set_pending_work(cpu);
+    held.unheld(|| assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 2));
+    drop(held);
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn pending_writer_is_not_starved_by_readers() {
+    use irq_safety::RwLockFairness;
+    static LOCK: RwLockIrqSafe<usize> = RwLockIrqSafe::with_fairness(0, RwLockFairness::WriterPreferring);
+
+    let first = LOCK.read();
+    let writer = std::thread::spawn(|| *LOCK.write() += 1);
+    // Once the writer is pending, new readers back off even though the lock is only read-locked.
+    while let Some(reader) = LOCK.try_read() {
+        drop(reader);
+        std::thread::yield_now();
+    }
+    let readers = std::thread::spawn(|| {
+        for _ in 0..100 {
+            assert_eq!(*LOCK.read(), 1);
+        }
+    });
+    drop(first);
+    writer.join().unwrap();
+    readers.join().unwrap();
+}
+
+#[test]
+fn phase_fair_reader_gets_in_after_one_write_phase() {
+    use irq_safety::RwLockFairness;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static LOCK: RwLockIrqSafe<usize> = RwLockIrqSafe::with_fairness(0, RwLockFairness::PhaseFair);
+    static PRIORITY: AtomicBool = AtomicBool::new(false);
+    static READ: AtomicBool = AtomicBool::new(false);
+    const NEXT_WRITE: usize = 1 << 40;
+
+    let reader = std::thread::spawn(|| {
+        // A waiting reader holds interrupts once it has waited through a write phase,
+        // which fires this handler while a writer is still in that phase or the next one.
+        sim::register_irq_handler(|| if LOCK.writer_count() > 0 {
+            PRIORITY.store(true, Ordering::SeqCst);
+        });
+        let seen = *LOCK.read();
+        READ.store(true, Ordering::SeqCst);
+        seen
+    });
+    // Another writer keeps a write pending most of the time, so the reader needs priority to get in.
+    let writer = std::thread::spawn(|| while !READ.load(Ordering::SeqCst) {
+        *LOCK.write() += 1;
+    });
+    // A stream of write phases, until the reader has priority or got in without needing it.
+    loop {
+        let mut guard = LOCK.write();
+        *guard += 1;
+        if PRIORITY.load(Ordering::SeqCst) || READ.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    // The next writer backs off until the reader got in.
+    *LOCK.write() += NEXT_WRITE;
+    assert!(reader.join().unwrap() < NEXT_WRITE);
+    writer.join().unwrap();
+}
+
+#[test]
+fn handler_reading_during_pending_write_does_not_hang() {
+    use irq_safety::RwLockFairness;
+    static LOCK: RwLockIrqSafe<usize> = RwLockIrqSafe::with_fairness(0, RwLockFairness::WriterPreferring);
+
+    let reader = std::thread::spawn(|| {
+        let guard = LOCK.read();
+        // Hold the read guard until the main thread's writer is pending.
+        while let Some(nested) = LOCK.try_read() {
+            drop(nested);
+            std::thread::yield_now();
+        }
+        drop(guard);
+    });
+    while LOCK.reader_count() == 0 {
+        std::thread::yield_now();
+    }
+
+    sim::set_spin_limit(10_000);
+    sim::register_irq_handler(|| assert_eq!(*LOCK.read(), 0));
+    // Interrupts stay held while the writer is pending,
+    // so the handler never spins on a lock that this CPU is waiting for.
+    let mut guard = LOCK.write();
+    let injected = sim::injected_irq_count();
+    assert!(injected > 0);
+    *guard += 1;
+    sim::clear_irq_handlers();
+    drop(guard);
+    reader.join().unwrap();
+    assert_eq!(*LOCK.read(), 1);
+}
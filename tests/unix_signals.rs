@@ -0,0 +1,148 @@
+//! Tests for the `unix_signals` backend, using real signals delivered to the current process.
+
+#![cfg(all(feature = "unix_signals", not(feature = "sim")))]
+
+use irq_safety::{hold_interrupts, interrupts_enabled, set_masked_signals, MutexIrqSafe, RwLockIrqSafe};
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+
+static USR1_COUNT: AtomicUsize = AtomicUsize::new(0);
+static COUNTER: MutexIrqSafe<usize> = MutexIrqSafe::new(0);
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+static TABLE: RwLockIrqSafe<[usize; 4]> = RwLockIrqSafe::new([0; 4]);
+
+extern "C" fn on_sigusr1(_: libc::c_int) {
+    USR1_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+extern "C" fn on_sigusr2(_: libc::c_int) {
+    *COUNTER.lock() += 1;
+    TABLE.read();
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn install(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+    set_masked_signals(&[libc::SIGUSR1, libc::SIGUSR2]);
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as usize;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(libc::sigaction(signal, &action, std::ptr::null_mut()), 0);
+    }
+}
+
+#[test]
+fn held_signals_are_deferred_until_drop() {
+    install(libc::SIGUSR1, on_sigusr1);
+    assert!(interrupts_enabled());
+
+    let before = USR1_COUNT.load(Ordering::SeqCst);
+    let outer = hold_interrupts();
+    assert!(!interrupts_enabled());
+    {
+        let _inner = hold_interrupts();
+        unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR1) };
+        assert_eq!(USR1_COUNT.load(Ordering::SeqCst), before);
+    }
+    // Dropping the inner guard must restore the outer guard's mask, not unblock the signal.
+    assert!(!interrupts_enabled());
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), before);
+
+    drop(outer);
+    assert!(interrupts_enabled());
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), before + 1);
+}
+
+#[test]
+fn wait_for_interrupt_does_not_lose_wakeups() {
+    install(libc::SIGUSR1, on_sigusr1);
+
+    let before = USR1_COUNT.load(Ordering::SeqCst);
+    let held = hold_interrupts();
+    // The signal arrives before we wait, while it is still blocked.
+    unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR1) };
+    let held = held.wait_for_interrupt();
+    assert!(USR1_COUNT.load(Ordering::SeqCst) > before);
+    assert!(!interrupts_enabled());
+    drop(held);
+    assert!(interrupts_enabled());
+}
+
+#[test]
+fn locks_shared_with_signal_handler_do_not_deadlock() {
+    install(libc::SIGUSR2, on_sigusr2);
+
+    let done = AtomicBool::new(false);
+    let iterations = 20_000;
+    thread::scope(|s| {
+        let (id_sender, id_receiver) = std::sync::mpsc::channel();
+        {
+            let done = &done;
+            s.spawn(move || {
+                id_sender.send(unsafe { libc::pthread_self() }).unwrap();
+                for i in 0..iterations {
+                    *COUNTER.lock() += 1;
+                    TABLE.write()[i % 4] += 1;
+                }
+                done.store(true, Ordering::SeqCst);
+            });
+        }
+        let target_id = id_receiver.recv().unwrap();
+        while !done.load(Ordering::SeqCst) {
+            unsafe { libc::pthread_kill(target_id, libc::SIGUSR2) };
+            thread::yield_now();
+        }
+    });
+
+    let handled = HANDLED.load(Ordering::SeqCst);
+    assert_eq!(*COUNTER.lock(), iterations + handled);
+    assert_eq!(TABLE.read().iter().sum::<usize>(), iterations);
+}
+
+#[test]
+fn pending_work_runs_when_outermost_guard_unblocks_signals() {
+    use irq_safety::{CpuId, InterruptsRestoredHook};
+
+    static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+    static HOOK_RUNS: AtomicUsize = AtomicUsize::new(0);
+    std::thread_local! {
+        static CPU: usize = NEXT_CPU.fetch_add(1, Ordering::Relaxed);
+    }
+    struct ThreadCpu;
+    // SAFETY: each thread acts as its own CPU, with a unique id.
+    unsafe impl CpuId for ThreadCpu {
+        fn current_cpu() -> usize { CPU.with(|c| *c) }
+    }
+    struct CountRuns;
+    impl InterruptsRestoredHook for CountRuns {
+        fn interrupts_restored() {
+            assert!(interrupts_enabled());
+            HOOK_RUNS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    set_masked_signals(&[libc::SIGUSR1, libc::SIGUSR2]);
+    irq_safety::register_interrupts_restored_hook::<ThreadCpu, CountRuns>();
+    let cpu = ThreadCpu::current_cpu();
+    let outer = hold_interrupts();
+    {
+        let _inner = hold_interrupts();
+        irq_safety::set_pending_work(cpu);
+    }
+    // The inner guard did not unblock any signals, so the work is still pending.
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 0);
+    assert!(irq_safety::has_pending_work(cpu));
+    drop(outer);
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 1);
+    assert!(!irq_safety::has_pending_work(cpu));
+
+    // Temporarily restoring interrupts runs pending work too.
+    let mut held = hold_interrupts();
+    irq_safety::set_pending_work(cpu);
+    held.unheld(|| assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 2));
+    drop(held);
+    assert_eq!(HOOK_RUNS.load(Ordering::SeqCst), 2);
+}